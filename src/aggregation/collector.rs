@@ -0,0 +1,405 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use tantivy::collector::{Collector, SegmentCollector};
+use tantivy::schema::Schema;
+use tantivy::{DocId, Score, SegmentOrdinal, SegmentReader};
+
+use crate::fast_field::{BucketKey, FastField};
+
+use super::facet::FacetAgg;
+use super::histogram::HistogramAgg;
+use super::range::RangeAgg;
+use super::stats::{Stats, StatsSegmentAgg};
+use super::terms::TermsAgg;
+use super::{AggReq, AggResult, AggsResp, Bucket};
+
+/// Aggregation splitting the documents into buckets
+pub trait BucketAgg: Send + Sync {
+    fn for_segment(&self, segment: &SegmentReader) -> tantivy::Result<Box<dyn SegmentBucketAgg>>;
+
+    /// Keys of the buckets returned even when no document falls into them
+    fn empty_keys(&self) -> Vec<BucketKey> {
+        Vec::new()
+    }
+
+    /// Orders the merged buckets and drops the ones not to return
+    fn select(&self, buckets: &mut Vec<(BucketKey, IntermediateBucket)>);
+}
+
+pub trait SegmentBucketAgg {
+    /// Sets `ids` to the distinct segment local ids of the buckets the document falls into
+    fn bucket_ids(&mut self, doc: DocId, ids: &mut Vec<u64>);
+
+    fn key(&mut self, id: u64) -> tantivy::Result<BucketKey>;
+}
+
+/// Aggregation request validated against the schema
+enum Aggregation {
+    Stats(FastField),
+    Buckets(Box<dyn BucketAgg>, Aggs),
+}
+
+impl Aggregation {
+    fn new(schema: &Schema, req: &AggReq) -> crate::Result<Self> {
+        Ok(match req {
+            AggReq::Facet(req) => Aggregation::Buckets(
+                Box::new(FacetAgg::new(schema, req)?),
+                Aggs::new(schema, &req.aggs)?,
+            ),
+            AggReq::Terms(req) => Aggregation::Buckets(
+                Box::new(TermsAgg::new(schema, req)?),
+                Aggs::new(schema, &req.aggs)?,
+            ),
+            AggReq::Stats(req) => Aggregation::Stats(FastField::numeric(schema, &req.field)?),
+            AggReq::Histogram(req) => Aggregation::Buckets(
+                Box::new(HistogramAgg::new(schema, req)?),
+                Aggs::new(schema, &req.aggs)?,
+            ),
+            AggReq::DateHistogram(req) => Aggregation::Buckets(
+                Box::new(HistogramAgg::date(schema, req)?),
+                Aggs::new(schema, &req.aggs)?,
+            ),
+            AggReq::Range(req) => Aggregation::Buckets(
+                Box::new(RangeAgg::new(schema, req)?),
+                Aggs::new(schema, &req.aggs)?,
+            ),
+        })
+    }
+
+    fn for_segment(&self, segment: &SegmentReader) -> tantivy::Result<SegmentAgg> {
+        Ok(match self {
+            Aggregation::Stats(field) => {
+                SegmentAgg::Stats(Box::new(StatsSegmentAgg::new(*field, segment)?))
+            }
+            Aggregation::Buckets(agg, sub_aggs) => SegmentAgg::Buckets {
+                agg: agg.for_segment(segment)?,
+                sub_aggs: sub_aggs.for_segment(segment)?,
+                ids: Vec::new(),
+            },
+        })
+    }
+
+    fn empty(&self) -> Intermediate {
+        match self {
+            Aggregation::Stats(_) => Intermediate::Stats(Stats::default()),
+            Aggregation::Buckets(_, _) => Intermediate::Buckets(HashMap::new()),
+        }
+    }
+
+    fn finalize(&self, result: Intermediate) -> AggResult {
+        match (self, result) {
+            (Aggregation::Stats(_), Intermediate::Stats(stats)) => AggResult::Stats(stats.result()),
+            (Aggregation::Buckets(agg, sub_aggs), Intermediate::Buckets(mut buckets)) => {
+                for key in agg.empty_keys() {
+                    buckets.entry(key).or_insert_with(|| IntermediateBucket {
+                        doc_count: 0,
+                        sub_aggs: sub_aggs.empty(),
+                    });
+                }
+                let mut buckets = buckets.into_iter().collect();
+                agg.select(&mut buckets);
+                let buckets = buckets
+                    .into_iter()
+                    .map(|(key, bucket)| Bucket {
+                        key,
+                        doc_count: bucket.doc_count,
+                        aggs: sub_aggs.finalize(bucket.sub_aggs),
+                    })
+                    .collect();
+                AggResult::Buckets { buckets }
+            }
+            _ => unreachable!("result of another aggregation"),
+        }
+    }
+}
+
+/// Aggregations in the order of their results
+struct Aggs(Vec<(String, Aggregation)>);
+
+impl Aggs {
+    fn new<'a>(
+        schema: &Schema,
+        aggs: impl IntoIterator<Item = (&'a String, &'a AggReq)>,
+    ) -> crate::Result<Self> {
+        aggs.into_iter()
+            .map(|(name, agg)| Ok((name.clone(), Aggregation::new(schema, agg)?)))
+            .collect::<crate::Result<_>>()
+            .map(Self)
+    }
+
+    fn for_segment(&self, segment: &SegmentReader) -> tantivy::Result<Vec<SegmentAgg>> {
+        self.0.iter().map(|(_, agg)| agg.for_segment(segment)).collect()
+    }
+
+    fn empty(&self) -> Vec<Intermediate> {
+        self.0.iter().map(|(_, agg)| agg.empty()).collect()
+    }
+
+    fn finalize(&self, results: Vec<Intermediate>) -> AggsResp {
+        self.0
+            .iter()
+            .zip(results)
+            .map(|((name, agg), result)| (name.clone(), agg.finalize(result)))
+            .collect()
+    }
+}
+
+/// Segment level aggregation, its state is kept apart to have one per bucket of the parent
+enum SegmentAgg {
+    Stats(Box<StatsSegmentAgg>),
+    Buckets {
+        agg: Box<dyn SegmentBucketAgg>,
+        sub_aggs: Vec<SegmentAgg>,
+        ids: Vec<u64>,
+    },
+}
+
+enum SegmentState {
+    Stats(Stats),
+    /// buckets by segment local id
+    Buckets(HashMap<u64, SegmentBucket>),
+}
+
+struct SegmentBucket {
+    doc_count: u64,
+    sub_aggs: Vec<SegmentState>,
+}
+
+/// Number of buckets the aggregations of a segment may still create
+struct BucketBudget {
+    left: usize,
+    exceeded: bool,
+}
+
+impl BucketBudget {
+    fn take(&mut self) -> bool {
+        if self.left == 0 {
+            self.exceeded = true;
+            return false;
+        }
+        self.left -= 1;
+        true
+    }
+}
+
+impl SegmentAgg {
+    fn new_state(&self) -> SegmentState {
+        match self {
+            SegmentAgg::Stats(_) => SegmentState::Stats(Stats::default()),
+            SegmentAgg::Buckets { .. } => SegmentState::Buckets(HashMap::new()),
+        }
+    }
+
+    fn collect(&mut self, doc: DocId, state: &mut SegmentState, budget: &mut BucketBudget) {
+        match (self, state) {
+            (SegmentAgg::Stats(agg), SegmentState::Stats(stats)) => agg.collect(doc, stats),
+            (SegmentAgg::Buckets { agg, sub_aggs, ids }, SegmentState::Buckets(buckets)) => {
+                agg.bucket_ids(doc, ids);
+                for id in ids.iter() {
+                    let bucket = match buckets.entry(*id) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) if budget.take() => entry.insert(SegmentBucket {
+                            doc_count: 0,
+                            sub_aggs: sub_aggs.iter().map(SegmentAgg::new_state).collect(),
+                        }),
+                        Entry::Vacant(_) => continue,
+                    };
+                    bucket.doc_count += 1;
+                    for (sub_agg, sub_state) in sub_aggs.iter_mut().zip(&mut bucket.sub_aggs) {
+                        sub_agg.collect(doc, sub_state, budget);
+                    }
+                }
+            }
+            _ => unreachable!("state of another aggregation"),
+        }
+    }
+
+    fn harvest(&mut self, state: SegmentState) -> tantivy::Result<Intermediate> {
+        Ok(match (self, state) {
+            (SegmentAgg::Stats(_), SegmentState::Stats(stats)) => Intermediate::Stats(stats),
+            (SegmentAgg::Buckets { agg, sub_aggs, .. }, SegmentState::Buckets(buckets)) => {
+                let mut result = HashMap::with_capacity(buckets.len());
+                for (id, bucket) in buckets {
+                    let bucket = IntermediateBucket {
+                        doc_count: bucket.doc_count,
+                        sub_aggs: harvest_all(sub_aggs, bucket.sub_aggs)?,
+                    };
+                    merge_bucket(&mut result, agg.key(id)?, bucket);
+                }
+                Intermediate::Buckets(result)
+            }
+            _ => unreachable!("state of another aggregation"),
+        })
+    }
+}
+
+fn harvest_all(aggs: &mut [SegmentAgg], states: Vec<SegmentState>) -> tantivy::Result<Vec<Intermediate>> {
+    aggs.iter_mut()
+        .zip(states)
+        .map(|(agg, state)| agg.harvest(state))
+        .collect()
+}
+
+/// Aggregation result mergeable across segments
+pub enum Intermediate {
+    Stats(Stats),
+    Buckets(HashMap<BucketKey, IntermediateBucket>),
+}
+
+pub struct IntermediateBucket {
+    pub doc_count: u64,
+    sub_aggs: Vec<Intermediate>,
+}
+
+impl Intermediate {
+    /// Number of buckets, including the ones of the sub aggregations
+    fn bucket_count(&self) -> usize {
+        match self {
+            Intermediate::Stats(_) => 0,
+            Intermediate::Buckets(buckets) => {
+                buckets.len()
+                    + buckets
+                        .values()
+                        .flat_map(|bucket| &bucket.sub_aggs)
+                        .map(Intermediate::bucket_count)
+                        .sum::<usize>()
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Intermediate) {
+        match (self, other) {
+            (Intermediate::Stats(stats), Intermediate::Stats(other)) => stats.merge(&other),
+            (Intermediate::Buckets(buckets), Intermediate::Buckets(other)) => {
+                for (key, other) in other {
+                    merge_bucket(buckets, key, other);
+                }
+            }
+            _ => unreachable!("result of another aggregation"),
+        }
+    }
+}
+
+fn merge_bucket(
+    buckets: &mut HashMap<BucketKey, IntermediateBucket>,
+    key: BucketKey,
+    bucket: IntermediateBucket,
+) {
+    match buckets.entry(key) {
+        Entry::Occupied(mut entry) => entry.get_mut().merge(bucket),
+        Entry::Vacant(entry) => {
+            entry.insert(bucket);
+        }
+    }
+}
+
+impl IntermediateBucket {
+    fn merge(&mut self, other: IntermediateBucket) {
+        self.doc_count += other.doc_count;
+        for (sub_agg, other) in self.sub_aggs.iter_mut().zip(other.sub_aggs) {
+            sub_agg.merge(other);
+        }
+    }
+}
+
+/// Computes the aggregations of a search request, fails once they create
+/// more than `max_buckets` buckets
+pub struct AggsCollector {
+    aggs: Aggs,
+    max_buckets: usize,
+}
+
+impl AggsCollector {
+    pub fn new<'a>(
+        schema: &Schema,
+        aggs: impl IntoIterator<Item = (&'a String, &'a AggReq)>,
+        max_buckets: usize,
+    ) -> crate::Result<Self> {
+        Ok(Self {
+            aggs: Aggs::new(schema, aggs)?,
+            max_buckets,
+        })
+    }
+
+    fn too_many_buckets(&self) -> crate::Error {
+        crate::error::invalid_query(format!(
+            "Aggregations create more than {} buckets, narrow the query or widen the intervals",
+            self.max_buckets
+        ))
+    }
+}
+
+impl Collector for AggsCollector {
+    type Fruit = crate::Result<AggsResp>;
+    type Child = AggsSegmentCollector;
+
+    fn for_segment(
+        &self,
+        _segment_local_id: SegmentOrdinal,
+        segment: &SegmentReader,
+    ) -> tantivy::Result<Self::Child> {
+        let aggs = self.aggs.for_segment(segment)?;
+        let states = aggs.iter().map(SegmentAgg::new_state).collect();
+        Ok(AggsSegmentCollector {
+            aggs,
+            states,
+            budget: BucketBudget {
+                left: self.max_buckets,
+                exceeded: false,
+            },
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<tantivy::Result<Option<Vec<Intermediate>>>>,
+    ) -> tantivy::Result<crate::Result<AggsResp>> {
+        let mut results = self.aggs.empty();
+        for segment_results in segment_fruits {
+            let segment_results = match segment_results? {
+                Some(segment_results) => segment_results,
+                None => return Ok(Err(self.too_many_buckets())),
+            };
+            for (result, segment_result) in results.iter_mut().zip(segment_results) {
+                result.merge(segment_result);
+            }
+        }
+        // segments may have different keys
+        if results.iter().map(Intermediate::bucket_count).sum::<usize>() > self.max_buckets {
+            return Ok(Err(self.too_many_buckets()));
+        }
+        Ok(Ok(self.aggs.finalize(results)))
+    }
+}
+
+pub struct AggsSegmentCollector {
+    aggs: Vec<SegmentAgg>,
+    states: Vec<SegmentState>,
+    budget: BucketBudget,
+}
+
+impl SegmentCollector for AggsSegmentCollector {
+    /// `None` when the aggregations create too many buckets
+    type Fruit = tantivy::Result<Option<Vec<Intermediate>>>;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        if self.budget.exceeded {
+            return;
+        }
+        for (agg, state) in self.aggs.iter_mut().zip(&mut self.states) {
+            agg.collect(doc, state, &mut self.budget);
+        }
+    }
+
+    fn harvest(mut self) -> Self::Fruit {
+        if self.budget.exceeded {
+            return Ok(None);
+        }
+        harvest_all(&mut self.aggs, self.states).map(Some)
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use tantivy::collector::{FacetCollector, FacetCounts};
+use tantivy::fastfield::FacetReader;
+use tantivy::schema::{Facet, Field, FieldType, Schema};
+use tantivy::{DocId, SegmentReader, TantivyError};
+
+use crate::fast_field::BucketKey;
+
+use super::collector::{BucketAgg, IntermediateBucket, SegmentBucketAgg};
+use super::terms::most_frequent;
+use super::{AggResult, AggsReq, AggsResp, Bucket};
+
+/// Separator of the levels in the encoded facets
+const FACET_SEP_BYTE: u8 = 0;
+
+fn default_path() -> String {
+    "/".to_string()
+}
+
+fn default_size() -> usize {
+    10
+}
+
+#[derive(Deserialize)]
+pub struct FacetAggReq {
+    pub field: String,
+    /// facet whose children are counted
+    #[serde(default = "default_path")]
+    pub path: String,
+    #[serde(default = "default_size")]
+    pub size: usize,
+    #[serde(default)]
+    pub aggs: AggsReq,
+}
+
+/// Buckets documents by the children of a facet their facets belong to
+pub struct FacetAgg {
+    field: Field,
+    facet: Facet,
+    size: usize,
+}
+
+impl FacetAgg {
+    pub fn new(schema: &Schema, req: &FacetAggReq) -> crate::Result<Self> {
+        Ok(Self {
+            field: facet_field(schema, &req.field)?,
+            facet: Facet::from_text(&req.path).map_err(crate::error::value_parsing_err)?,
+            size: req.size,
+        })
+    }
+}
+
+fn facet_field(schema: &Schema, field_name: &str) -> crate::Result<Field> {
+    let field = schema
+        .get_field(field_name)
+        .ok_or_else(|| crate::error::field_not_exist(field_name.to_string()))?;
+    if !matches!(schema.get_field_entry(field).field_type(), FieldType::HierarchicalFacet(_)) {
+        return Err(crate::error::invalid_query(format!(
+            "Field '{}' is not a facet field",
+            field_name
+        )));
+    }
+    Ok(field)
+}
+
+/// Facet aggregations without sub aggregations only need the counts of tantivy
+pub fn facet_collector(schema: &Schema, req: &FacetAggReq) -> crate::Result<(FacetCollector, Facet)> {
+    let field = facet_field(schema, &req.field)?;
+    let facet = Facet::from_text(&req.path).map_err(crate::error::value_parsing_err)?;
+    let mut collector = FacetCollector::for_field(field);
+    collector.add_facet(facet.clone());
+    Ok((collector, facet))
+}
+
+pub fn facet_result(counts: FacetCounts, facet: Facet, size: usize) -> AggResult {
+    let buckets = counts
+        .top_k(facet, size)
+        .into_iter()
+        .map(|(facet, doc_count)| Bucket {
+            key: BucketKey::Str(facet.to_string()),
+            doc_count,
+            aggs: AggsResp::new(),
+        })
+        .collect();
+    AggResult::Buckets { buckets }
+}
+
+impl BucketAgg for FacetAgg {
+    fn for_segment(&self, segment: &SegmentReader) -> tantivy::Result<Box<dyn SegmentBucketAgg>> {
+        let reader = segment.facet_reader(self.field)?;
+        let mut prefix = self.facet.encoded_str().as_bytes().to_vec();
+        if !self.facet.is_root() {
+            prefix.push(FACET_SEP_BYTE);
+        }
+
+        // the facets below the parent are a contiguous range of the sorted dictionary
+        let mut child_ids = HashMap::new();
+        let mut children = Vec::<Facet>::new();
+        let mut stream = reader.facet_dict().range().ge(&prefix).into_stream()?;
+        while stream.advance() {
+            let key = stream.key();
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let child_len = key[prefix.len()..]
+                .iter()
+                .position(|byte| *byte == FACET_SEP_BYTE)
+                .unwrap_or(key.len() - prefix.len());
+            if child_len == 0 {
+                continue;
+            }
+            let child = &key[..prefix.len() + child_len];
+            if children.last().map(|last| last.encoded_str().as_bytes()) != Some(child) {
+                let facet = Facet::from_encoded(child.to_vec())
+                    .map_err(|err| TantivyError::SystemError(err.to_string()))?;
+                children.push(facet);
+            }
+            child_ids.insert(stream.term_ord(), children.len() as u64 - 1);
+        }
+
+        Ok(Box::new(FacetSegmentAgg {
+            reader,
+            child_ids,
+            children,
+            ords: Vec::new(),
+        }))
+    }
+
+    fn select(&self, buckets: &mut Vec<(BucketKey, IntermediateBucket)>) {
+        most_frequent(buckets, self.size);
+    }
+}
+
+/// Uses the indexes of the parent children as bucket ids
+struct FacetSegmentAgg {
+    reader: FacetReader,
+    /// child id by facet ordinal, facets outside of the parent are missing
+    child_ids: HashMap<u64, u64>,
+    children: Vec<Facet>,
+    ords: Vec<u64>,
+}
+
+impl SegmentBucketAgg for FacetSegmentAgg {
+    fn bucket_ids(&mut self, doc: DocId, ids: &mut Vec<u64>) {
+        self.reader.facet_ords(doc, &mut self.ords);
+        ids.clear();
+        ids.extend(self.ords.iter().filter_map(|ord| self.child_ids.get(ord)));
+        ids.sort_unstable();
+        ids.dedup();
+    }
+
+    fn key(&mut self, id: u64) -> tantivy::Result<BucketKey> {
+        Ok(BucketKey::Str(self.children[id as usize].to_string()))
+    }
+}
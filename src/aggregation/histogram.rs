@@ -0,0 +1,213 @@
+use std::convert::TryFrom;
+
+use serde::Deserialize;
+use tantivy::chrono::{Datelike, NaiveDate, NaiveDateTime};
+use tantivy::schema::Schema;
+use tantivy::{f64_to_u64, DocId, SegmentReader, TantivyError};
+
+use crate::fast_field::{BucketKey, FastField, FastType, NumericValues};
+
+use super::collector::{BucketAgg, IntermediateBucket, SegmentBucketAgg};
+use super::AggsReq;
+
+const MINUTE: f64 = 60.0;
+const HOUR: f64 = 60.0 * MINUTE;
+const DAY: f64 = 24.0 * HOUR;
+const WEEK: f64 = 7.0 * DAY;
+/// 1970-01-01 is a thursday, weeks start on monday
+const WEEK_OFFSET: f64 = -3.0 * DAY;
+
+#[derive(Deserialize)]
+pub struct HistogramAggReq {
+    pub field: String,
+    pub interval: f64,
+    /// shifts the bucket boundaries
+    #[serde(default)]
+    pub offset: f64,
+    #[serde(default)]
+    pub aggs: AggsReq,
+}
+
+#[derive(Deserialize)]
+pub struct DateHistogramAggReq {
+    pub field: String,
+    /// `year`, `quarter`, `month`, `week`, `day`, `hour`, `minute`, `second`
+    /// or a fixed interval like `30s`, `15m`, `12h`, `7d`
+    pub interval: String,
+    #[serde(default)]
+    pub aggs: AggsReq,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Interval {
+    Fixed { width: f64, offset: f64 },
+    /// calendar interval, the values are timestamps in seconds
+    Months(i64),
+}
+
+impl Interval {
+    fn parse_date(interval: &str) -> crate::Result<Self> {
+        let fixed = |width| Interval::Fixed { width, offset: 0.0 };
+        Ok(match interval {
+            "year" => Interval::Months(12),
+            "quarter" => Interval::Months(3),
+            "month" => Interval::Months(1),
+            "week" => Interval::Fixed {
+                width: WEEK,
+                offset: WEEK_OFFSET,
+            },
+            "day" => fixed(DAY),
+            "hour" => fixed(HOUR),
+            "minute" => fixed(MINUTE),
+            "second" => fixed(1.0),
+            _ => {
+                let invalid = || {
+                    crate::error::invalid_query(format!("Invalid date histogram interval '{}'", interval))
+                };
+                let unit_pos = interval.len().checked_sub(1).ok_or_else(invalid)?;
+                let unit = match interval.get(unit_pos..) {
+                    Some("s") => 1.0,
+                    Some("m") => MINUTE,
+                    Some("h") => HOUR,
+                    Some("d") => DAY,
+                    Some("w") => WEEK,
+                    _ => return Err(invalid()),
+                };
+                let count = interval[..unit_pos].parse::<u32>().map_err(|_| invalid())?;
+                if count == 0 {
+                    return Err(invalid());
+                }
+                fixed(count as f64 * unit)
+            }
+        })
+    }
+
+    fn bucket_id(&self, value: f64) -> Option<i64> {
+        match *self {
+            Interval::Fixed { width, offset } => Some(((value - offset) / width).floor() as i64),
+            Interval::Months(months) => {
+                let date = NaiveDateTime::from_timestamp_opt(value as i64, 0)?;
+                let month = date.year() as i64 * 12 + date.month0() as i64;
+                Some(month.div_euclid(months))
+            }
+        }
+    }
+
+    /// Returns the lower bound of the bucket, `None` for dates chrono can't represent
+    fn bucket_start(&self, id: i64) -> Option<f64> {
+        match *self {
+            Interval::Fixed { width, offset } => Some(id as f64 * width + offset),
+            Interval::Months(months) => {
+                let month = id.checked_mul(months)?;
+                let year = i32::try_from(month.div_euclid(12)).ok()?;
+                let date = NaiveDate::from_ymd_opt(year, month.rem_euclid(12) as u32 + 1, 1)?;
+                Some(date.and_hms_opt(0, 0, 0)?.timestamp() as f64)
+            }
+        }
+    }
+}
+
+/// Buckets the values of a numeric field per interval
+pub struct HistogramAgg {
+    field: FastField,
+    interval: Interval,
+}
+
+impl HistogramAgg {
+    pub fn new(schema: &Schema, req: &HistogramAggReq) -> crate::Result<Self> {
+        if !(req.interval.is_finite() && req.interval > 0.0) {
+            return Err(crate::error::invalid_query(format!(
+                "Histogram interval must be positive, got {}",
+                req.interval
+            )));
+        }
+        Ok(Self {
+            field: FastField::numeric(schema, &req.field)?,
+            interval: Interval::Fixed {
+                width: req.interval,
+                offset: req.offset,
+            },
+        })
+    }
+
+    pub fn date(schema: &Schema, req: &DateHistogramAggReq) -> crate::Result<Self> {
+        let field = FastField::numeric(schema, &req.field)?;
+        if field.fast_type() != FastType::Date {
+            return Err(crate::error::invalid_query(format!(
+                "Field '{}' is not a date field",
+                req.field
+            )));
+        }
+        Ok(Self {
+            field,
+            interval: Interval::parse_date(&req.interval)?,
+        })
+    }
+}
+
+impl BucketAgg for HistogramAgg {
+    fn for_segment(&self, segment: &SegmentReader) -> tantivy::Result<Box<dyn SegmentBucketAgg>> {
+        Ok(Box::new(HistogramSegmentAgg {
+            date: self.field.fast_type() == FastType::Date,
+            interval: self.interval,
+            values: NumericValues::new(self.field, segment)?,
+        }))
+    }
+
+    fn select(&self, buckets: &mut Vec<(BucketKey, IntermediateBucket)>) {
+        buckets.sort_by(|(a, _), (b, _)| a.cmp(b));
+    }
+}
+
+/// Uses the interval indexes as bucket ids
+struct HistogramSegmentAgg {
+    date: bool,
+    interval: Interval,
+    values: NumericValues,
+}
+
+impl SegmentBucketAgg for HistogramSegmentAgg {
+    fn bucket_ids(&mut self, doc: DocId, ids: &mut Vec<u64>) {
+        ids.clear();
+        for value in self.values.values(doc) {
+            ids.extend(self.interval.bucket_id(value).map(|id| id as u64));
+        }
+        ids.sort_unstable();
+        ids.dedup();
+    }
+
+    fn key(&mut self, id: u64) -> tantivy::Result<BucketKey> {
+        let start = self.interval.bucket_start(id as i64).ok_or_else(|| {
+            TantivyError::InvalidArgument(format!("Histogram bucket {} is out of the date range", id as i64))
+        })?;
+        Ok(if self.date {
+            BucketKey::Date(start as i64)
+        } else {
+            BucketKey::F64(f64_to_u64(start))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_date_interval() {
+        assert_eq!(Interval::parse_date("12h").unwrap(), Interval::Fixed { width: 12.0 * HOUR, offset: 0.0 });
+        assert_eq!(Interval::parse_date("quarter").unwrap(), Interval::Months(3));
+        assert!(Interval::parse_date("0d").is_err());
+        assert!(Interval::parse_date("h").is_err());
+        assert!(Interval::parse_date("").is_err());
+
+        // 2021-05-19T10:00:00Z, a wednesday
+        let value = 1621418400.0;
+        let week = Interval::parse_date("week").unwrap();
+        // 2021-05-17T00:00:00Z
+        assert_eq!(week.bucket_start(week.bucket_id(value).unwrap()), Some(1621209600.0));
+        let quarter = Interval::Months(3);
+        // 2021-04-01T00:00:00Z
+        assert_eq!(quarter.bucket_start(quarter.bucket_id(value).unwrap()), Some(1617235200.0));
+        assert_eq!(Interval::Months(12).bucket_start(i64::MAX / 12), None);
+    }
+}
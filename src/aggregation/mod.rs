@@ -0,0 +1,108 @@
+mod collector;
+mod facet;
+mod histogram;
+mod range;
+mod stats;
+mod terms;
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tantivy::collector::{Collector, MultiCollector};
+use tantivy::schema::Schema;
+
+use crate::fast_field::BucketKey;
+
+use collector::AggsCollector;
+use facet::{facet_collector, facet_result, FacetAggReq};
+use histogram::{DateHistogramAggReq, HistogramAggReq};
+use range::RangeAggReq;
+use stats::{StatsAggReq, StatsResult};
+use terms::TermsAggReq;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AggReq {
+    /// counts of the children of a hierarchical facet
+    Facet(FacetAggReq),
+    /// counts of the most frequent values of a fast field
+    Terms(TermsAggReq),
+    /// count, min, max, avg and sum of a numeric fast field
+    Stats(StatsAggReq),
+    /// counts of the values per fixed size interval
+    Histogram(HistogramAggReq),
+    /// counts of the dates per fixed or calendar interval
+    DateHistogram(DateHistogramAggReq),
+    /// counts of the values per user defined range
+    Range(RangeAggReq),
+}
+
+/// Aggregations by name
+pub type AggsReq = BTreeMap<String, AggReq>;
+
+#[derive(Serialize)]
+pub struct Bucket {
+    pub key: BucketKey,
+    pub doc_count: u64,
+    /// results of the sub aggregations by name
+    #[serde(flatten)]
+    pub aggs: AggsResp,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum AggResult {
+    Buckets { buckets: Vec<Bucket> },
+    Stats(StatsResult),
+}
+
+pub type AggsResp = BTreeMap<String, AggResult>;
+
+type MultiFruit = <MultiCollector<'static> as Collector>::Fruit;
+
+/// Takes the aggregation fruit out of the `MultiCollector` fruits
+type Extractor = Box<dyn FnOnce(&mut MultiFruit) -> crate::Result<AggsResp>>;
+
+/// Handles to extract aggregation results from the `MultiCollector` fruits.
+/// Facet aggregations without sub aggregations use the `FacetCollector` of
+/// tantivy, the others are computed together by an `AggsCollector`.
+pub struct AggsHandles(Vec<Extractor>);
+
+impl AggsHandles {
+    pub fn new(
+        schema: &Schema,
+        aggs: &AggsReq,
+        max_buckets: usize,
+        collector: &mut MultiCollector,
+    ) -> crate::Result<Self> {
+        let mut extractors = Vec::<Extractor>::new();
+        let mut bucket_aggs = Vec::new();
+        for (name, agg) in aggs {
+            match agg {
+                AggReq::Facet(req) if req.aggs.is_empty() => {
+                    let (facet_collector, facet) = facet_collector(schema, req)?;
+                    let handle = collector.add_collector(facet_collector);
+                    let (name, size) = (name.clone(), req.size);
+                    extractors.push(Box::new(move |fruits| {
+                        let result = facet_result(handle.extract(fruits), facet, size);
+                        Ok(std::iter::once((name, result)).collect())
+                    }));
+                }
+                _ => bucket_aggs.push((name, agg)),
+            }
+        }
+        if !bucket_aggs.is_empty() {
+            let handle = collector.add_collector(AggsCollector::new(schema, bucket_aggs, max_buckets)?);
+            extractors.push(Box::new(move |fruits| handle.extract(fruits)));
+        }
+        Ok(Self(extractors))
+    }
+
+    pub fn extract(self, fruits: &mut MultiFruit) -> crate::Result<AggsResp> {
+        let mut resp = AggsResp::new();
+        for extractor in self.0 {
+            resp.extend(extractor(fruits)?);
+        }
+        Ok(resp)
+    }
+}
@@ -0,0 +1,139 @@
+use serde::Deserialize;
+use tantivy::chrono::DateTime;
+use tantivy::schema::Schema;
+use tantivy::{DocId, SegmentReader};
+
+use crate::fast_field::{BucketKey, FastField, FastType, NumericValues};
+
+use super::collector::{BucketAgg, IntermediateBucket, SegmentBucketAgg};
+use super::AggsReq;
+
+/// Number, or RFC 3339 date for date fields
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum RangeValue {
+    Number(f64),
+    Date(String),
+}
+
+#[derive(Deserialize)]
+pub struct RangeReq {
+    /// defaults to `from-to`, with `*` for a missing bound
+    pub key: Option<String>,
+    /// inclusive lower bound
+    pub from: Option<RangeValue>,
+    /// exclusive upper bound
+    pub to: Option<RangeValue>,
+}
+
+#[derive(Deserialize)]
+pub struct RangeAggReq {
+    pub field: String,
+    pub ranges: Vec<RangeReq>,
+    #[serde(default)]
+    pub aggs: AggsReq,
+}
+
+#[derive(Clone)]
+struct Range {
+    key: String,
+    from: f64,
+    to: f64,
+}
+
+impl Range {
+    fn new(field: FastField, req: &RangeReq) -> crate::Result<Self> {
+        let bound = |value: &Option<RangeValue>, unbounded: f64| -> crate::Result<(f64, String)> {
+            match value {
+                None => Ok((unbounded, "*".to_string())),
+                Some(RangeValue::Number(number)) => Ok((*number, number.to_string())),
+                Some(RangeValue::Date(date)) if field.fast_type() == FastType::Date => {
+                    let timestamp = DateTime::parse_from_rfc3339(date)
+                        .map_err(crate::error::value_parsing_err)?
+                        .timestamp();
+                    Ok((timestamp as f64, date.clone()))
+                }
+                Some(RangeValue::Date(date)) => Err(crate::error::invalid_query(format!(
+                    "Range bound '{}' is not a number",
+                    date
+                ))),
+            }
+        };
+        let (from, from_text) = bound(&req.from, f64::NEG_INFINITY)?;
+        let (to, to_text) = bound(&req.to, f64::INFINITY)?;
+        Ok(Self {
+            key: req.key.clone().unwrap_or_else(|| format!("{}-{}", from_text, to_text)),
+            from,
+            to,
+        })
+    }
+
+    fn contains(&self, value: f64) -> bool {
+        self.from <= value && value < self.to
+    }
+}
+
+/// Buckets the values of a numeric field per range, ranges may overlap
+pub struct RangeAgg {
+    field: FastField,
+    ranges: Vec<Range>,
+}
+
+impl RangeAgg {
+    pub fn new(schema: &Schema, req: &RangeAggReq) -> crate::Result<Self> {
+        let field = FastField::numeric(schema, &req.field)?;
+        let ranges = req.ranges
+            .iter()
+            .map(|range| Range::new(field, range))
+            .collect::<crate::Result<_>>()?;
+        Ok(Self { field, ranges })
+    }
+
+    fn position(&self, key: &BucketKey) -> Option<usize> {
+        self.ranges
+            .iter()
+            .position(|range| matches!(key, BucketKey::Str(key) if *key == range.key))
+    }
+}
+
+impl BucketAgg for RangeAgg {
+    fn for_segment(&self, segment: &SegmentReader) -> tantivy::Result<Box<dyn SegmentBucketAgg>> {
+        Ok(Box::new(RangeSegmentAgg {
+            ranges: self.ranges.clone(),
+            values: NumericValues::new(self.field, segment)?,
+        }))
+    }
+
+    fn empty_keys(&self) -> Vec<BucketKey> {
+        self.ranges.iter().map(|range| BucketKey::Str(range.key.clone())).collect()
+    }
+
+    fn select(&self, buckets: &mut Vec<(BucketKey, IntermediateBucket)>) {
+        buckets.sort_by_key(|(key, _)| self.position(key));
+    }
+}
+
+/// Uses the indexes of the ranges as bucket ids
+struct RangeSegmentAgg {
+    ranges: Vec<Range>,
+    values: NumericValues,
+}
+
+impl SegmentBucketAgg for RangeSegmentAgg {
+    fn bucket_ids(&mut self, doc: DocId, ids: &mut Vec<u64>) {
+        ids.clear();
+        for value in self.values.values(doc) {
+            for (id, range) in self.ranges.iter().enumerate() {
+                if range.contains(value) {
+                    ids.push(id as u64);
+                }
+            }
+        }
+        ids.sort_unstable();
+        ids.dedup();
+    }
+
+    fn key(&mut self, id: u64) -> tantivy::Result<BucketKey> {
+        Ok(BucketKey::Str(self.ranges[id as usize].key.clone()))
+    }
+}
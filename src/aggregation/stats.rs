@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use tantivy::{DocId, SegmentReader};
+
+use crate::fast_field::{FastField, NumericValues};
+
+#[derive(Deserialize)]
+pub struct StatsAggReq {
+    pub field: String,
+}
+
+#[derive(Serialize)]
+pub struct StatsResult {
+    pub count: u64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub avg: Option<f64>,
+    pub sum: f64,
+}
+
+#[derive(Clone, Copy)]
+pub struct Stats {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl Stats {
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    pub fn merge(&mut self, other: &Stats) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn result(&self) -> StatsResult {
+        let not_empty = self.count > 0;
+        StatsResult {
+            count: self.count,
+            min: Some(self.min).filter(|_| not_empty),
+            max: Some(self.max).filter(|_| not_empty),
+            avg: Some(self.sum / self.count as f64).filter(|_| not_empty),
+            sum: self.sum,
+        }
+    }
+}
+
+/// Adds every value of the documents, multivalued fields included
+pub struct StatsSegmentAgg {
+    values: NumericValues,
+}
+
+impl StatsSegmentAgg {
+    pub fn new(field: FastField, segment: &SegmentReader) -> tantivy::Result<Self> {
+        Ok(Self {
+            values: NumericValues::new(field, segment)?,
+        })
+    }
+
+    pub fn collect(&mut self, doc: DocId, stats: &mut Stats) {
+        for value in self.values.values(doc) {
+            stats.add(value);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use serde::Deserialize;
+use tantivy::schema::Schema;
+use tantivy::{DocId, SegmentReader};
+
+use crate::fast_field::{BucketKey, FastField, FastValues};
+
+use super::collector::{BucketAgg, IntermediateBucket, SegmentBucketAgg};
+use super::AggsReq;
+
+fn default_size() -> usize {
+    10
+}
+
+#[derive(Deserialize)]
+pub struct TermsAggReq {
+    pub field: String,
+    /// number of the most frequent terms to return
+    #[serde(default = "default_size")]
+    pub size: usize,
+    #[serde(default)]
+    pub aggs: AggsReq,
+}
+
+/// Buckets documents by the distinct values of a fast field
+pub struct TermsAgg {
+    field: FastField,
+    size: usize,
+}
+
+impl TermsAgg {
+    pub fn new(schema: &Schema, req: &TermsAggReq) -> crate::Result<Self> {
+        Ok(Self {
+            field: FastField::new(schema, &req.field)?,
+            size: req.size,
+        })
+    }
+}
+
+impl BucketAgg for TermsAgg {
+    fn for_segment(&self, segment: &SegmentReader) -> tantivy::Result<Box<dyn SegmentBucketAgg>> {
+        Ok(Box::new(TermsSegmentAgg {
+            field: self.field,
+            values: self.field.reader(segment)?,
+        }))
+    }
+
+    fn select(&self, buckets: &mut Vec<(BucketKey, IntermediateBucket)>) {
+        most_frequent(buckets, self.size);
+    }
+}
+
+/// Keeps the `size` buckets with the most documents
+pub fn most_frequent(buckets: &mut Vec<(BucketKey, IntermediateBucket)>, size: usize) {
+    buckets.sort_by(|(a_key, a), (b_key, b)| {
+        b.doc_count.cmp(&a.doc_count).then_with(|| a_key.cmp(b_key))
+    });
+    buckets.truncate(size);
+}
+
+/// Uses the raw fast field values as bucket ids
+struct TermsSegmentAgg {
+    field: FastField,
+    values: FastValues,
+}
+
+impl SegmentBucketAgg for TermsSegmentAgg {
+    fn bucket_ids(&mut self, doc: DocId, ids: &mut Vec<u64>) {
+        self.values.values(doc, ids);
+        ids.sort_unstable();
+        ids.dedup();
+    }
+
+    fn key(&mut self, id: u64) -> tantivy::Result<BucketKey> {
+        // facet ordinals are local to the segment
+        Ok(match self.values.facet(id)? {
+            Some(facet) => BucketKey::Str(facet.to_string()),
+            None => self.field.key(id),
+        })
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Alias {
+    pub indices: Vec<String>,
+    /// index receiving the writes, the only index when there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write_index: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AddAlias {
+    pub alias: String,
+    pub index: String,
+    #[serde(default)]
+    pub is_write_index: bool,
+}
+
+#[derive(Deserialize)]
+pub struct RemoveAlias {
+    pub alias: String,
+    pub index: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AliasAction {
+    Add(AddAlias),
+    Remove(RemoveAlias),
+}
+
+/// Actions applied together, so that an alias can be moved to another index atomically
+#[derive(Deserialize)]
+pub struct AliasActionsReq {
+    pub actions: Vec<AliasAction>,
+}
+
+/// Aliases by name
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Aliases(BTreeMap<String, Alias>);
+
+impl Aliases {
+    pub fn get(&self, name: &str) -> Option<&Alias> {
+        self.0.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Alias)> {
+        self.0.iter()
+    }
+
+    /// Index of the writes through the alias
+    pub fn write_target(&self, name: &str) -> crate::Result<Option<&str>> {
+        match self.0.get(name) {
+            Some(alias) => match (&alias.write_index, alias.indices.as_slice()) {
+                (Some(index), _) | (None, [index]) => Ok(Some(index)),
+                (None, _) => Err(crate::error::alias_without_write_index(name.to_string())),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Index of the alias for the operations on a single index, which can't
+    /// pick one of several indices for the caller
+    pub fn single_target(&self, name: &str) -> crate::Result<Option<&str>> {
+        match self.0.get(name) {
+            Some(alias) => match alias.indices.as_slice() {
+                [index] => Ok(Some(index)),
+                _ => Err(crate::error::alias_with_several_indices(name.to_string())),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Adding an index marked as the write index replaces the previous one,
+    /// removing an index the alias doesn't have fails
+    pub fn apply(&mut self, action: AliasAction) -> crate::Result<()> {
+        match action {
+            AliasAction::Add(AddAlias { alias, index, is_write_index }) => {
+                let alias = self.0.entry(alias).or_default();
+                if !alias.indices.contains(&index) {
+                    alias.indices.push(index.clone());
+                }
+                if is_write_index {
+                    alias.write_index = Some(index);
+                }
+            }
+            AliasAction::Remove(RemoveAlias { alias: name, index }) => {
+                let alias = match self.0.get_mut(&name) {
+                    Some(alias) if alias.indices.contains(&index) => alias,
+                    _ => return Err(crate::error::alias_not_exist(name, index)),
+                };
+                alias.remove_index(&index);
+                if alias.indices.is_empty() {
+                    self.0.remove(&name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes a deleted index from every alias
+    pub fn remove_index(&mut self, index: &str) {
+        for alias in self.0.values_mut() {
+            alias.remove_index(index);
+        }
+        self.0.retain(|_, alias| !alias.indices.is_empty());
+    }
+}
+
+impl Alias {
+    fn remove_index(&mut self, index: &str) {
+        self.indices.retain(|name| name != index);
+        if self.write_index.as_deref() == Some(index) {
+            self.write_index = None;
+        }
+    }
+}
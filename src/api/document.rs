@@ -1,7 +1,11 @@
+use std::time::Duration;
+
 use actix_web::{web, HttpResponse};
+use futures::StreamExt;
 use serde::Deserialize;
 
 use crate::dto::*;
+use crate::multi_search;
 use crate::security::{authc::User, authz::IndexPrivileges};
 use crate::AppState;
 
@@ -9,6 +13,24 @@ use crate::AppState;
 pub struct AddDocOptions {
     #[serde(default)]
     commit: bool,
+    #[serde(default)]
+    refresh: Refresh,
+}
+
+/// lines above which the rest of a bulk request is refused
+const MAX_BULK_LINES: usize = 100_000;
+/// size above which a bulk line is refused, as it is buffered until its end
+const MAX_BULK_LINE_SIZE: usize = 10 * 1024 * 1024;
+
+fn default_keep_alive() -> u64 {
+    60
+}
+
+#[derive(Deserialize)]
+pub struct PointInTimeOptions {
+    /// seconds the point in time lives after its last use
+    #[serde(default = "default_keep_alive")]
+    keep_alive: u64,
 }
 
 pub async fn add_document(
@@ -18,6 +40,7 @@ pub async fn add_document(
     query: web::Query<AddDocOptions>,
     body: web::Bytes,
 ) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.write_index(&index_name)?;
     state
         .access_control
         .check_index(&user, &index_name, IndexPrivileges::WRITE)?;
@@ -27,18 +50,112 @@ pub async fn add_document(
     let req = AddDocReq {
         doc,
         commit: query.commit,
+        refresh: query.refresh,
     };
     index.add_document(req).await?;
 
     Ok(HttpResponse::Ok().into())
 }
 
+pub async fn update_document(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name, id)): web::Path<(String, String)>,
+    query: web::Query<AddDocOptions>,
+    body: web::Bytes,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.write_index(&index_name)?;
+    state
+        .access_control
+        .check_index(&user, &index_name, IndexPrivileges::WRITE)?;
+
+    let index = state.indices.index(&index_name).await?;
+    let doc = String::from_utf8(body.to_vec())?;
+    let req = UpdateDocReq {
+        id,
+        doc,
+        commit: query.commit,
+        refresh: query.refresh,
+    };
+    index.update_document(req).await?;
+
+    Ok(HttpResponse::Ok().into())
+}
+
+pub async fn bulk(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name,)): web::Path<(String,)>,
+    mut body: web::Payload,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.write_index(&index_name)?;
+    state
+        .access_control
+        .check_index(&user, &index_name, IndexPrivileges::WRITE)?;
+
+    let index = state.indices.index(&index_name).await?;
+
+    let mut resp = BulkResp::default();
+    // number of the first line of `buf`
+    let mut line_num = 1;
+    // only the lines of the current chunk and the incomplete one are kept in memory
+    let mut buf = Vec::new();
+    let mut limit_error = None;
+    while let Some(chunk) = body.next().await {
+        buf.extend_from_slice(&chunk?);
+        let mut lines = match buf.iter().rposition(|b| *b == b'\n') {
+            Some(pos) => {
+                let tail = buf.split_off(pos + 1);
+                std::mem::replace(&mut buf, tail)
+            }
+            None if buf.len() > MAX_BULK_LINE_SIZE => {
+                limit_error = Some(format!("Bulk line is limited to {} bytes", MAX_BULK_LINE_SIZE));
+                break;
+            }
+            None => continue,
+        };
+        let mut count = lines.iter().filter(|b| **b == b'\n').count();
+        if line_num + count > MAX_BULK_LINES + 1 {
+            // the lines up to the limit are still applied
+            count = MAX_BULK_LINES + 1 - line_num;
+            let end = match count {
+                0 => 0,
+                _ => lines.iter().enumerate().filter(|(_, b)| **b == b'\n').nth(count - 1).map_or(0, |(i, _)| i),
+            };
+            lines.truncate(end);
+            limit_error = Some(format!("Bulk request is limited to {} lines", MAX_BULK_LINES));
+        }
+        resp.items.extend(index.bulk(lines, line_num).await?);
+        line_num += count;
+        if limit_error.is_some() {
+            break;
+        }
+    }
+    if limit_error.is_none() && line_num > MAX_BULK_LINES && !buf.iter().all(u8::is_ascii_whitespace) {
+        limit_error = Some(format!("Bulk request is limited to {} lines", MAX_BULK_LINES));
+    }
+    match limit_error {
+        Some(error) => resp.items.push(BulkItemResult {
+            line: line_num,
+            error: Some(error),
+        }),
+        None => resp.items.extend(index.bulk(buf, line_num).await?),
+    }
+    resp.errors = resp.items.iter().any(|item| item.error.is_some());
+
+    log::debug!("Committing bulk");
+    index.commit().await?;
+
+    Ok(HttpResponse::Ok().json(resp))
+}
+
 pub async fn delete_by_term(
     state: web::Data<AppState>,
     user: User,
     web::Path((index_name,)): web::Path<(String,)>,
     req: web::Query<DeleteByTermReq>,
 ) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.write_index(&index_name)?;
     state
         .access_control
         .check_index(&user, &index_name, IndexPrivileges::WRITE)?;
@@ -49,18 +166,249 @@ pub async fn delete_by_term(
     Ok(HttpResponse::Ok().finish())
 }
 
+/// The matching documents are deleted by key after the search, so a document written
+/// in between with the key of a matching one is deleted as well
+pub async fn delete_by_query(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name,)): web::Path<(String,)>,
+    web::Json(req): web::Json<DeleteByQueryReq>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.write_index(&index_name)?;
+    state
+        .access_control
+        .check_index(&user, &index_name, IndexPrivileges::WRITE)?;
+
+    let index = state.indices.index(&index_name).await?;
+    let resp = index.delete_by_query(req).await?;
+
+    Ok(HttpResponse::Ok().json(resp))
+}
+
 pub async fn search_documents(
     state: web::Data<AppState>,
     user: User,
     web::Path((index_name,)): web::Path<(String,)>,
     query: web::Query<SearchReq>,
 ) -> crate::Result<HttpResponse> {
+    let docs = search(&state, &user, &index_name, query.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(docs))
+}
+
+pub async fn search_documents_json(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name,)): web::Path<(String,)>,
+    web::Json(req): web::Json<SearchReq>,
+) -> crate::Result<HttpResponse> {
+    let docs = search(&state, &user, &index_name, req).await?;
+
+    Ok(HttpResponse::Ok().json(docs))
+}
+
+/// Searches one index, or several given as a comma separated list of names, aliases
+/// and wildcard patterns
+async fn search(
+    state: &AppState,
+    user: &User,
+    index_expr: &str,
+    req: SearchReq,
+) -> crate::Result<SearchResp> {
+    let resolved = state.indices.resolve(index_expr)?;
+    for index in &resolved {
+        state
+            .access_control
+            .check_index(user, &index.name, IndexPrivileges::READ)?;
+    }
+    let (indices, skipped) = state.indices.resolved_indices(resolved)?;
+    let mut resp = match indices.as_slice() {
+        [(_, index)] => index.search(req).await?,
+        _ => multi_search::search(indices, req).await?,
+    };
+    resp.skipped_indices = skipped;
+    Ok(resp)
+}
+
+pub async fn explain_document(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name, id)): web::Path<(String, String)>,
+    query: web::Query<ExplainReq>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.single_index(&index_name)?;
+    state
+        .access_control
+        .check_index(&user, &index_name, IndexPrivileges::READ)?;
+
+    let index = state.indices.index(&index_name).await?;
+    let doc = index.explain(id, query.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(doc))
+}
+
+pub async fn explain_document_json(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name, id)): web::Path<(String, String)>,
+    web::Json(req): web::Json<ExplainReq>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.single_index(&index_name)?;
+    state
+        .access_control
+        .check_index(&user, &index_name, IndexPrivileges::READ)?;
+
+    let index = state.indices.index(&index_name).await?;
+    let doc = index.explain(id, req).await?;
+
+    Ok(HttpResponse::Ok().json(doc))
+}
+
+pub async fn get_document(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name, field, value)): web::Path<(String, String, String)>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.single_index(&index_name)?;
+    state
+        .access_control
+        .check_index(&user, &index_name, IndexPrivileges::READ)?;
+
+    let index = state.indices.index(&index_name).await?;
+    let doc = index.get_document(field, value).await?;
+
+    Ok(HttpResponse::Ok().json(doc))
+}
+
+pub async fn document_exists(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name, field, value)): web::Path<(String, String, String)>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.single_index(&index_name)?;
+    state
+        .access_control
+        .check_index(&user, &index_name, IndexPrivileges::READ)?;
+
+    let index = state.indices.index(&index_name).await?;
+    if index.document_exists(field, value).await? {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
+
+pub async fn get_documents(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name,)): web::Path<(String,)>,
+    web::Json(req): web::Json<MultiGetReq>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.single_index(&index_name)?;
     state
         .access_control
         .check_index(&user, &index_name, IndexPrivileges::READ)?;
 
     let index = state.indices.index(&index_name).await?;
-    let docs = index.search(query.into_inner()).await?;
+    let docs = index.get_documents(req).await?;
 
     Ok(HttpResponse::Ok().json(docs))
 }
+
+pub async fn count_documents(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name,)): web::Path<(String,)>,
+    query: web::Query<CountReq>,
+) -> crate::Result<HttpResponse> {
+    let count = count(&state, &user, &index_name, query.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(count))
+}
+
+pub async fn count_documents_json(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name,)): web::Path<(String,)>,
+    web::Json(req): web::Json<CountReq>,
+) -> crate::Result<HttpResponse> {
+    let count = count(&state, &user, &index_name, req).await?;
+
+    Ok(HttpResponse::Ok().json(count))
+}
+
+/// Counts in one index or in several like `search`
+async fn count(
+    state: &AppState,
+    user: &User,
+    index_expr: &str,
+    req: CountReq,
+) -> crate::Result<CountResp> {
+    let resolved = state.indices.resolve(index_expr)?;
+    for index in &resolved {
+        state
+            .access_control
+            .check_index(user, &index.name, IndexPrivileges::READ)?;
+    }
+    let (indices, skipped) = state.indices.resolved_indices(resolved)?;
+    let mut resp = match indices.as_slice() {
+        [(_, index)] => index.count(req).await?,
+        _ => multi_search::count(indices.into_iter().map(|(_, index)| index).collect(), req).await?,
+    };
+    resp.skipped_indices = skipped;
+    Ok(resp)
+}
+
+pub async fn export_documents(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name,)): web::Path<(String,)>,
+    query: web::Query<ExportReq>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.single_index(&index_name)?;
+    state
+        .access_control
+        .check_index(&user, &index_name, IndexPrivileges::READ)?;
+
+    let index = state.indices.index(&index_name).await?;
+    let chunks = index.export(query.into_inner()).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(chunks))
+}
+
+pub async fn open_point_in_time(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name,)): web::Path<(String,)>,
+    query: web::Query<PointInTimeOptions>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.single_index(&index_name)?;
+    state
+        .access_control
+        .check_index(&user, &index_name, IndexPrivileges::READ)?;
+
+    let index = state.indices.index(&index_name).await?;
+    let id = index
+        .open_point_in_time(Duration::from_secs(query.keep_alive))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(PointInTimeResp { id }))
+}
+
+pub async fn close_point_in_time(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name, id)): web::Path<(String, String)>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.single_index(&index_name)?;
+    state
+        .access_control
+        .check_index(&user, &index_name, IndexPrivileges::READ)?;
+
+    let index = state.indices.index(&index_name).await?;
+    let closed = index.close_point_in_time(&id)?;
+
+    Ok(HttpResponse::Ok().json(ClosePointInTimeResp { closed }))
+}
@@ -1,9 +1,26 @@
 use actix_web::{web, HttpResponse};
+use serde::Deserialize;
 
+use crate::aliases::AliasActionsReq;
+use crate::dto::{AllStats, AnalyzeReq};
 use crate::index_config::IndexConfig;
-use crate::security::{authc::User, authz::SystemPrivileges};
+use crate::merge_policy::MergePolicyConfig;
+use crate::security::{
+    authc::User,
+    authz::{IndexPrivileges, SystemPrivileges},
+};
 use crate::AppState;
 
+fn default_max_segments() -> usize {
+    1
+}
+
+#[derive(Deserialize)]
+pub struct ForceMergeOptions {
+    #[serde(default = "default_max_segments")]
+    max_segments: usize,
+}
+
 pub async fn create_index(
     state: web::Data<AppState>,
     user: User,
@@ -13,7 +30,7 @@ pub async fn create_index(
     state
         .access_control
         .check_system(&user, SystemPrivileges::MANAGE_INDICES)?;
-    state.indices.create_index(index_name, &index_conf).await?;
+    state.indices.create_index(index_name, index_conf).await?;
     Ok(HttpResponse::Ok().into())
 }
 
@@ -28,3 +45,159 @@ pub async fn delete_index(
     state.indices.delete_index(&index_name).await?;
     Ok(HttpResponse::Ok().into())
 }
+
+pub async fn list_indices(state: web::Data<AppState>, user: User) -> crate::Result<HttpResponse> {
+    state
+        .access_control
+        .check_system(&user, SystemPrivileges::MANAGE_INDICES)?;
+    let indices = state.indices.list_indices()?;
+    Ok(HttpResponse::Ok().json(indices))
+}
+
+pub async fn index_stats(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name,)): web::Path<(String,)>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.single_index(&index_name)?;
+    state
+        .access_control
+        .check_index(&user, &index_name, IndexPrivileges::READ)?;
+    let index = state.indices.index(&index_name).await?;
+    let stats = index.stats().await?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+pub async fn analyze(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name,)): web::Path<(String,)>,
+    web::Json(req): web::Json<AnalyzeReq>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.single_index(&index_name)?;
+    state
+        .access_control
+        .check_index(&user, &index_name, IndexPrivileges::READ)?;
+    let index = state.indices.index(&index_name).await?;
+    let resp = index.analyze(req)?;
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+/// Rebuilds the search analyzers of the index, e.g. after a synonyms file changed
+pub async fn reload_search_analyzers(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name,)): web::Path<(String,)>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.single_index(&index_name)?;
+    state
+        .access_control
+        .check_system(&user, SystemPrivileges::MANAGE_INDICES)?;
+    let index = state.indices.index(&index_name).await?;
+    index.reload_search_analyzers()?;
+    Ok(HttpResponse::Ok().into())
+}
+
+pub async fn all_stats(state: web::Data<AppState>, user: User) -> crate::Result<HttpResponse> {
+    state
+        .access_control
+        .check_system(&user, SystemPrivileges::MANAGE_INDICES)?;
+    let mut stats = AllStats::default();
+    for (name, index) in state.indices.open_indices()? {
+        let index_stats = index.stats().await?;
+        stats.total.add(&index_stats.totals);
+        stats.indices.insert(name, index_stats);
+    }
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+pub async fn list_aliases(state: web::Data<AppState>, user: User) -> crate::Result<HttpResponse> {
+    state
+        .access_control
+        .check_system(&user, SystemPrivileges::MANAGE_INDICES)?;
+    let aliases = state.indices.aliases()?;
+    Ok(HttpResponse::Ok().json(aliases))
+}
+
+pub async fn update_aliases(
+    state: web::Data<AppState>,
+    user: User,
+    web::Json(req): web::Json<AliasActionsReq>,
+) -> crate::Result<HttpResponse> {
+    state
+        .access_control
+        .check_system(&user, SystemPrivileges::MANAGE_INDICES)?;
+    state.indices.update_aliases(req)?;
+    Ok(HttpResponse::Ok().into())
+}
+
+/// Starts the merge as a task and returns it without waiting for the merge
+pub async fn force_merge(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name,)): web::Path<(String,)>,
+    query: web::Query<ForceMergeOptions>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.single_index(&index_name)?;
+    state
+        .access_control
+        .check_index(&user, &index_name, IndexPrivileges::WRITE)?;
+    let index = state.indices.index(&index_name).await?;
+    let task = state.tasks.start("force_merge", &index_name)?;
+    let id = task.id;
+    let max_segments = query.max_segments;
+    let state = state.clone();
+    actix_web::rt::spawn(async move {
+        let result = index.force_merge(max_segments).await;
+        if let Err(err) = state.tasks.finish(id, result) {
+            log::error!("Failed to finish task {}: {}", id, err);
+        }
+    });
+    Ok(HttpResponse::Ok().json(task))
+}
+
+pub async fn get_merge_policy(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name,)): web::Path<(String,)>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.single_index(&index_name)?;
+    state
+        .access_control
+        .check_system(&user, SystemPrivileges::MANAGE_INDICES)?;
+    let index = state.indices.index(&index_name).await?;
+    Ok(HttpResponse::Ok().json(index.merge_policy()?))
+}
+
+pub async fn set_merge_policy(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((index_name,)): web::Path<(String,)>,
+    web::Json(merge_policy): web::Json<MergePolicyConfig>,
+) -> crate::Result<HttpResponse> {
+    let index_name = state.indices.single_index(&index_name)?;
+    state
+        .access_control
+        .check_system(&user, SystemPrivileges::MANAGE_INDICES)?;
+    let index = state.indices.index(&index_name).await?;
+    index.set_merge_policy(merge_policy)?;
+    Ok(HttpResponse::Ok().into())
+}
+
+pub async fn list_tasks(state: web::Data<AppState>, user: User) -> crate::Result<HttpResponse> {
+    state
+        .access_control
+        .check_system(&user, SystemPrivileges::MANAGE_INDICES)?;
+    Ok(HttpResponse::Ok().json(state.tasks.list()?))
+}
+
+pub async fn get_task(
+    state: web::Data<AppState>,
+    user: User,
+    web::Path((id,)): web::Path<(u64,)>,
+) -> crate::Result<HttpResponse> {
+    state
+        .access_control
+        .check_system(&user, SystemPrivileges::MANAGE_INDICES)?;
+    Ok(HttpResponse::Ok().json(state.tasks.get(id)?))
+}
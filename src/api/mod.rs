@@ -1,6 +1,8 @@
 mod document;
 mod index;
 mod security;
+#[cfg(test)]
+mod test;
 
 use actix_cors::Cors;
 use actix_web::error::JsonPayloadError;
@@ -11,8 +13,17 @@ use serde_json::json;
 
 use crate::security::authc::authentication_handler;
 use crate::AppState;
-use document::{add_document, delete_by_term, search_documents};
-use index::{create_index, delete_index};
+use document::{
+    add_document, bulk, close_point_in_time, count_documents, count_documents_json, delete_by_query,
+    delete_by_term, document_exists, explain_document, explain_document_json, export_documents,
+    get_document, get_documents, open_point_in_time, search_documents, search_documents_json,
+    update_document,
+};
+use index::{
+    all_stats, analyze, create_index, delete_index, force_merge, get_merge_policy, get_task,
+    index_stats, list_aliases, list_indices, list_tasks, reload_search_analyzers, set_merge_policy,
+    update_aliases,
+};
 use security::{add_user, assign_permissions, list_users, list_users_permissions, remove_user};
 
 pub async fn run_server(state: AppState) -> crate::Result<()> {
@@ -52,6 +63,15 @@ fn config_routes(conf: &mut web::ServiceConfig) {
                 .service(web::resource("/").route(web::get().to(list_users_permissions)))
                 .service(web::resource("/{user}").route(web::put().to(assign_permissions))),
         )
+        .service(web::resource("/_indices").route(web::get().to(list_indices)))
+        .service(web::resource("/_stats").route(web::get().to(all_stats)))
+        .service(web::resource("/_tasks").route(web::get().to(list_tasks)))
+        .service(web::resource("/_tasks/{id}").route(web::get().to(get_task)))
+        .service(
+            web::resource("/_aliases")
+                .route(web::get().to(list_aliases))
+                .route(web::post().to(update_aliases)),
+        )
         .service(
             web::resource("/{index}")
                 .route(web::post().to(create_index))
@@ -60,8 +80,28 @@ fn config_routes(conf: &mut web::ServiceConfig) {
         .service(
             web::scope("/{index}")
                 .route("/", web::post().to(add_document))
+                .route("/_doc/{id}", web::put().to(update_document))
+                .route("/_doc/{field}/{value}", web::get().to(get_document))
+                .route("/_doc/{field}/{value}", web::head().to(document_exists))
+                .route("/_mget", web::post().to(get_documents))
+                .route("/_bulk", web::post().to(bulk))
                 .route("/_search", web::get().to(search_documents))
-                .route("/_delete_by_term", web::post().to(delete_by_term)),
+                .route("/_search", web::post().to(search_documents_json))
+                .route("/_count", web::get().to(count_documents))
+                .route("/_count", web::post().to(count_documents_json))
+                .route("/_explain/{id}", web::get().to(explain_document))
+                .route("/_explain/{id}", web::post().to(explain_document_json))
+                .route("/_export", web::get().to(export_documents))
+                .route("/_stats", web::get().to(index_stats))
+                .route("/_analyze", web::post().to(analyze))
+                .route("/_reload_search_analyzers", web::post().to(reload_search_analyzers))
+                .route("/_forcemerge", web::post().to(force_merge))
+                .route("/_merge_policy", web::get().to(get_merge_policy))
+                .route("/_merge_policy", web::put().to(set_merge_policy))
+                .route("/_pit", web::post().to(open_point_in_time))
+                .route("/_pit/{id}", web::delete().to(close_point_in_time))
+                .route("/_delete_by_term", web::post().to(delete_by_term))
+                .route("/_delete_by_query", web::post().to(delete_by_query)),
         );
 }
 
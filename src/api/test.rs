@@ -0,0 +1,717 @@
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::{test, web, App};
+use actix_web_httpauth::middleware::HttpAuthentication;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+use super::{config_routes, error_handler};
+use crate::config::{Api, AppConfig, CommitPolicy, Search};
+use crate::dto::ExportReq;
+use crate::security::authc::{authentication_handler, AddUserReq};
+use crate::AppState;
+
+/// Server state in a temporary data dir with a user allowed to do anything
+/// on the given indices
+struct TestApp {
+    state: web::Data<AppState>,
+    data_dir: TempDir,
+}
+
+impl TestApp {
+    fn new(indices: &[&str]) -> Self {
+        let data_dir = tempfile::tempdir().unwrap();
+        let state = AppState::from_config(Self::config(&data_dir)).unwrap();
+        state
+            .auth
+            .add_user(AddUserReq {
+                name: "test".to_string(),
+                password: "test".to_string(),
+            })
+            .unwrap();
+        let index = indices
+            .iter()
+            .map(|name| (name.to_string(), json!(["read", "write"])))
+            .collect::<serde_json::Map<_, _>>();
+        let permissions = json!({
+            "system": ["manage_security", "manage_indices"],
+            "index": index,
+        });
+        state
+            .access_control
+            .assign_permissions("test".to_string(), serde_json::from_value(permissions).unwrap())
+            .unwrap();
+        Self {
+            state: web::Data::new(state),
+            data_dir,
+        }
+    }
+
+    fn config(data_dir: &TempDir) -> AppConfig {
+        AppConfig {
+            api: Api {
+                listen: "127.0.0.1:0".parse().unwrap(),
+            },
+            search: Search {
+                data_dir: data_dir.path().to_path_buf(),
+                indexer_num_threads: Some(1),
+                indexer_heap_size: 15_000_000,
+                commit: CommitPolicy::default(),
+                max_buckets: 100,
+            },
+        }
+    }
+
+    /// Reopens the data dir like a restarted server, the test user is kept
+    fn restart(self) -> Self {
+        // the indices must be closed to release their writer locks
+        drop(self.state);
+        let state = AppState::from_config(Self::config(&self.data_dir)).unwrap();
+        Self {
+            state: web::Data::new(state),
+            data_dir: self.data_dir,
+        }
+    }
+
+    /// Sends a request as the test user, returns the status and the JSON body if any
+    async fn call(&self, method: Method, uri: &str, body: impl Into<web::Bytes>) -> (StatusCode, Value) {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(HttpAuthentication::basic(authentication_handler))
+                .app_data(self.state.clone())
+                .app_data(web::JsonConfig::default().error_handler(error_handler))
+                .configure(config_routes),
+        )
+        .await;
+        let req = test::TestRequest::with_uri(uri)
+            .method(method)
+            .header(header::AUTHORIZATION, format!("Basic {}", base64::encode("test:test")))
+            .header(header::CONTENT_TYPE, "application/json")
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        let status = resp.status();
+        let body = test::read_body(resp).await;
+        let body = if body.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&body).unwrap()
+        };
+        (status, body)
+    }
+
+    async fn call_json(&self, method: Method, uri: &str, body: Value) -> (StatusCode, Value) {
+        self.call(method, uri, body.to_string()).await
+    }
+
+    async fn create_index_with(&self, name: &str, config: &str) {
+        let (status, body) = self.call(Method::POST, &format!("/{}", name), config.to_string()).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    /// Creates an index with a primary key `id` and a `text` field
+    async fn create_index(&self, name: &str) {
+        // a raw string, as the schema wants `type` before `options`
+        let config = r#"{
+            "options": {
+                "primary_key": "id",
+                "default_search_fields": [{ "field": "text" }]
+            },
+            "schema": [
+                {
+                    "name": "id",
+                    "type": "u64",
+                    "options": { "indexed": true, "stored": true, "fast": "single" }
+                },
+                {
+                    "name": "text",
+                    "type": "text",
+                    "options": {
+                        "indexing": { "record": "position", "tokenizer": "default" },
+                        "stored": true
+                    }
+                }
+            ]
+        }"#;
+        self.create_index_with(name, config).await;
+    }
+
+    async fn count(&self, index: &str, query: &str) -> Value {
+        let (status, body) = self
+            .call_json(Method::POST, &format!("/{}/_count", index), json!({ "query": query }))
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        body["count"].clone()
+    }
+}
+
+#[actix_rt::test]
+async fn test_bulk() {
+    let app = TestApp::new(&["docs"]);
+    app.create_index("docs").await;
+
+    let lines = [
+        r#"{"index": {"id": 1, "text": "first"}}"#,
+        "not json",
+        "",
+        r#"{"update": {"id": 1, "text": "updated"}}"#,
+        r#"{"delete": {"field": "missing", "term": "1"}}"#,
+        r#"{"index": {"id": 2, "text": "second"}}"#,
+    ];
+    let (status, body) = app.call(Method::POST, "/docs/_bulk", lines.join("\n")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["errors"], json!(true));
+    let items = body["items"].as_array().unwrap();
+    let lines = items.iter().map(|item| item["line"].clone()).collect::<Vec<_>>();
+    assert_eq!(lines, vec![json!(1), json!(2), json!(4), json!(5), json!(6)]);
+    let failed = items
+        .iter()
+        .filter(|item| item.get("error").is_some())
+        .map(|item| item["line"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(failed, vec![json!(2), json!(5)]);
+
+    // the valid actions are applied and committed despite the failed ones
+    assert_eq!(app.count("docs", "*").await, json!(2));
+    assert_eq!(app.count("docs", "updated").await, json!(1));
+    assert_eq!(app.count("docs", "first").await, json!(0));
+}
+
+#[actix_rt::test]
+async fn test_refresh_wait_for() {
+    let app = TestApp::new(&["docs"]);
+    app.create_index("docs").await;
+
+    // returns once the background commit made the document searchable
+    let doc = json!({ "id": 1, "text": "first" });
+    let (status, body) = app.call_json(Method::POST, "/docs/?refresh=wait_for", doc).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(app.count("docs", "first").await, json!(1));
+
+    let doc = json!({ "id": 1, "text": "updated" });
+    let (status, body) = app.call_json(Method::PUT, "/docs/_doc/1?refresh=wait_for", doc).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(app.count("docs", "updated").await, json!(1));
+    assert_eq!(app.count("docs", "first").await, json!(0));
+}
+
+#[actix_rt::test]
+async fn test_facet_aggs() {
+    let app = TestApp::new(&["shop"]);
+    let config = r#"{
+        "schema": [
+            { "name": "category", "type": "hierarchical_facet", "options": { "indexed": true, "stored": false } },
+            { "name": "price", "type": "u64", "options": { "indexed": true, "stored": false, "fast": "single" } }
+        ]
+    }"#;
+    app.create_index_with("shop", config).await;
+    let docs = [
+        r#"{"index": {"category": "/electronics/phones", "price": 100}}"#,
+        r#"{"index": {"category": "/electronics/phones", "price": 300}}"#,
+        r#"{"index": {"category": "/electronics/laptops", "price": 1000}}"#,
+        r#"{"index": {"category": "/books", "price": 20}}"#,
+    ];
+    let (status, body) = app.call(Method::POST, "/shop/_bulk", docs.join("\n")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["errors"], json!(false));
+
+    let req = json!({
+        "query": { "match_all": {} },
+        "limit": 0,
+        "aggs": {
+            "top": { "facet": { "field": "category" } },
+            "electronics": { "facet": { "field": "category", "path": "/electronics" } },
+            "with_price": {
+                "facet": {
+                    "field": "category",
+                    "path": "/electronics",
+                    "aggs": { "price": { "stats": { "field": "price" } } }
+                }
+            }
+        }
+    });
+    let (status, body) = app.call_json(Method::POST, "/shop/_search", req).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let aggs = &body["aggs"];
+    assert_eq!(
+        aggs["top"],
+        json!({ "buckets": [
+            { "key": "/electronics", "doc_count": 3 },
+            { "key": "/books", "doc_count": 1 },
+        ] })
+    );
+    assert_eq!(
+        aggs["electronics"],
+        json!({ "buckets": [
+            { "key": "/electronics/phones", "doc_count": 2 },
+            { "key": "/electronics/laptops", "doc_count": 1 },
+        ] })
+    );
+    let buckets = aggs["with_price"]["buckets"].as_array().unwrap();
+    assert_eq!(buckets[0]["key"], json!("/electronics/phones"));
+    assert_eq!(buckets[0]["price"]["sum"], json!(400.0));
+    assert_eq!(buckets[1]["key"], json!("/electronics/laptops"));
+    assert_eq!(buckets[1]["price"]["sum"], json!(1000.0));
+}
+
+#[actix_rt::test]
+async fn test_max_buckets() {
+    let app = TestApp::new(&["shop"]);
+    let config = r#"{
+        "schema": [
+            { "name": "price", "type": "u64", "options": { "indexed": true, "stored": false, "fast": "single" } }
+        ]
+    }"#;
+    app.create_index_with("shop", config).await;
+    let docs = (0..150)
+        .map(|price| json!({ "index": { "price": price } }).to_string())
+        .collect::<Vec<_>>();
+    let (status, body) = app.call(Method::POST, "/shop/_bulk", docs.join("\n")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let histogram = |interval| {
+        json!({
+            "query": { "match_all": {} },
+            "limit": 0,
+            "aggs": { "prices": { "histogram": { "field": "price", "interval": interval } } }
+        })
+    };
+    // the test app allows 100 buckets
+    let (status, body) = app.call_json(Method::POST, "/shop/_search", histogram(1.0)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    let (status, body) = app.call_json(Method::POST, "/shop/_search", histogram(10.0)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["aggs"]["prices"]["buckets"].as_array().unwrap().len(), 15);
+}
+
+#[actix_rt::test]
+async fn test_delete_by_query() {
+    let app = TestApp::new(&["docs", "unstored", "terms"]);
+    app.create_index("docs").await;
+    let docs = [
+        r#"{"index": {"id": 1, "text": "red apple"}}"#,
+        r#"{"index": {"id": 2, "text": "red cherry"}}"#,
+        r#"{"index": {"id": 3, "text": "red apple"}}"#,
+        r#"{"index": {"id": 4, "text": "green apple"}}"#,
+        // another document with the key 4, which adding doesn't replace
+        r#"{"index": {"id": 4, "text": "red apple"}}"#,
+    ];
+    let (status, body) = app.call(Method::POST, "/docs/_bulk", docs.join("\n")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    // deleting the key 4 would delete the green apple too
+    let req = json!({ "query": "text:red AND text:apple", "commit": true });
+    let (status, body) = app.call_json(Method::POST, "/docs/_delete_by_query", req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    assert_eq!(app.count("docs", "*").await, json!(5));
+
+    let req = json!({ "query": "id:1 OR id:2 OR id:3", "commit": true });
+    let (status, body) = app.call_json(Method::POST, "/docs/_delete_by_query", req).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["deleted"], json!(3));
+    assert_eq!(app.count("docs", "*").await, json!(2));
+
+    let req = json!({ "query": "id:4", "commit": true });
+    let (status, body) = app.call_json(Method::POST, "/docs/_delete_by_query", req).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["deleted"], json!(2));
+    assert_eq!(app.count("docs", "*").await, json!(0));
+
+    // the keys of the matching documents must be readable
+    let config = r#"{
+        "options": { "primary_key": "id" },
+        "schema": [{ "name": "id", "type": "u64", "options": { "indexed": true, "stored": false } }]
+    }"#;
+    let (status, body) = app.call(Method::POST, "/unstored", config.to_string()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+
+    // queries of terms are deleted by their terms, without reading the keys
+    let config = r#"{
+        "options": { "default_search_fields": [{ "field": "tag" }] },
+        "schema": [{ "name": "tag", "type": "text", "options": { "indexing": { "record": "basic", "tokenizer": "raw" }, "stored": false } }]
+    }"#;
+    app.create_index_with("terms", config).await;
+    let docs = [r#"{"index": {"tag": "red"}}"#, r#"{"index": {"tag": "green"}}"#, r#"{"index": {"tag": "blue"}}"#];
+    let (status, body) = app.call(Method::POST, "/terms/_bulk", docs.join("\n")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let req = json!({ "query": "red OR green", "commit": true });
+    let (status, body) = app.call_json(Method::POST, "/terms/_delete_by_query", req).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["deleted"], json!(2));
+    assert_eq!(app.count("terms", "*").await, json!(1));
+}
+
+#[actix_rt::test]
+async fn test_primary_key_upsert() {
+    let app = TestApp::new(&["docs", "tokenized"]);
+    let config = r#"{
+        "options": { "primary_key": "key" },
+        "schema": [{
+            "name": "key",
+            "type": "text",
+            "options": { "indexing": { "record": "basic", "tokenizer": "raw" }, "stored": true }
+        }]
+    }"#;
+    app.create_index_with("docs", config).await;
+    for _ in 0..2 {
+        let doc = json!({ "key": "Doc A" });
+        let (status, body) = app.call_json(Method::PUT, "/docs/_doc/Doc%20A?commit=true", doc).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+    assert_eq!(app.count("docs", "*").await, json!(1));
+
+    // the tokens of the key would never match the term deleted by an upsert
+    let config = r#"{
+        "options": { "primary_key": "key" },
+        "schema": [{
+            "name": "key",
+            "type": "text",
+            "options": { "indexing": { "record": "basic", "tokenizer": "default" }, "stored": true }
+        }]
+    }"#;
+    let (status, body) = app.call(Method::POST, "/tokenized", config.to_string()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+}
+
+#[actix_rt::test]
+async fn test_multi_search_sort_types() {
+    let app = TestApp::new(&["a", "b", "c"]);
+    let config = |rank_type: &str| {
+        format!(
+            r#"{{
+                "schema": [
+                    {{ "name": "rank", "type": "{}", "options": {{ "indexed": true, "stored": true, "fast": "single" }} }}
+                ]
+            }}"#,
+            rank_type
+        )
+    };
+    app.create_index_with("a", &config("u64")).await;
+    app.create_index_with("b", &config("u64")).await;
+    app.create_index_with("c", &config("i64")).await;
+    for (index, ranks) in [("a", [3, 1]), ("b", [2, 4]), ("c", [0, 5])] {
+        let docs = ranks
+            .iter()
+            .map(|rank| json!({ "index": { "rank": rank } }).to_string())
+            .collect::<Vec<_>>();
+        let (status, body) = app.call(Method::POST, &format!("/{}/_bulk", index), docs.join("\n")).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    let req = json!({ "query": { "match_all": {} }, "sort": [{ "field": "rank" }] });
+    let (status, body) = app.call_json(Method::POST, "/a,b/_search", req.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let ranks = body["docs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|doc| doc["doc"]["rank"][0].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(ranks, vec![json!(1), json!(2), json!(3), json!(4)]);
+
+    // u64 and i64 values don't compare as raw fast field values
+    let (status, body) = app.call_json(Method::POST, "/a,c/_search", req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+}
+
+#[actix_rt::test]
+async fn test_multi_search_skips_failed_indices() {
+    let app = TestApp::new(&["logs_a", "logs_b"]);
+    app.create_index("logs_a").await;
+    app.create_index("logs_b").await;
+    for index in ["logs_a", "logs_b"] {
+        let (status, body) = app
+            .call_json(Method::PUT, &format!("/{}/_doc/1?commit=true", index), json!({ "id": 1, "text": "hello" }))
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+    std::fs::remove_file(app.data_dir.path().join("logs_b").join("meta.json")).unwrap();
+    let app = app.restart();
+
+    let req = json!({ "query": "hello" });
+    let (status, body) = app.call_json(Method::POST, "/logs_*/_search", req.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["docs"].as_array().unwrap().len(), 1);
+    assert_eq!(body["skipped_indices"], json!(["logs_b"]));
+    let (status, body) = app.call_json(Method::POST, "/logs_*/_count", req.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body, json!({ "count": 1, "skipped_indices": ["logs_b"] }));
+
+    // a failed index named in the request is still an error
+    let (status, body) = app.call_json(Method::POST, "/logs_a,logs_b/_search", req).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{}", body);
+}
+
+#[actix_rt::test]
+async fn test_aliases() {
+    let app = TestApp::new(&["old", "new"]);
+    app.create_index("old").await;
+    app.create_index("new").await;
+    let actions = json!({ "actions": [
+        { "add": { "alias": "single", "index": "old" } },
+        { "add": { "alias": "all", "index": "old" } },
+        { "add": { "alias": "all", "index": "new", "is_write_index": true } },
+    ] });
+    let (status, body) = app.call_json(Method::POST, "/_aliases", actions).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    // writes go to the write index
+    let doc = json!({ "id": 1, "text": "old" });
+    let (status, body) = app.call_json(Method::POST, "/single/?commit=true", doc).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let doc = json!({ "id": 2, "text": "new" });
+    let (status, body) = app.call_json(Method::POST, "/all/?commit=true", doc).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(app.count("old", "*").await, json!(1));
+    assert_eq!(app.count("new", "*").await, json!(1));
+
+    // searches and counts read every index
+    assert_eq!(app.count("all", "*").await, json!(2));
+    let req = json!({ "query": { "match_all": {} } });
+    let (status, body) = app.call_json(Method::POST, "/all/_search", req).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["docs"].as_array().unwrap().len(), 2);
+
+    // other reads don't silently pick the write index
+    let (status, body) = app.call(Method::GET, "/all/_doc/id/2", "").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    let (status, body) = app.call(Method::GET, "/all/_stats", "").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    let (status, body) = app.call(Method::GET, "/single/_doc/id/1", "").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["text"], json!(["old"]));
+
+    // removing a missing alias fails and applies none of the actions
+    let actions = json!({ "actions": [
+        { "remove": { "alias": "single", "index": "old" } },
+        { "remove": { "alias": "single", "index": "new" } },
+    ] });
+    let (status, body) = app.call_json(Method::POST, "/_aliases", actions).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+    let (status, body) = app.call(Method::GET, "/single/_doc/id/1", "").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+#[actix_rt::test]
+async fn test_force_merge_task() {
+    let app = TestApp::new(&["docs"]);
+    app.create_index("docs").await;
+    // a segment per commit
+    for id in 0..3 {
+        let doc = json!({ "id": id, "text": "text" });
+        let (status, body) = app.call_json(Method::POST, "/docs/?commit=true", doc).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+    let (_, stats) = app.call(Method::GET, "/docs/_stats", "").await;
+    assert_eq!(stats["segments"].as_array().unwrap().len(), 3);
+
+    let (status, task) = app.call(Method::POST, "/docs/_forcemerge?max_segments=1", "").await;
+    assert_eq!(status, StatusCode::OK, "{}", task);
+    assert_eq!(task["action"], json!("force_merge"));
+    assert_eq!(task["index"], json!("docs"));
+
+    let uri = format!("/_tasks/{}", task["id"]);
+    let mut task = task;
+    for _ in 0..100 {
+        if task["status"] != json!("running") {
+            break;
+        }
+        actix_rt::time::delay_for(std::time::Duration::from_millis(50)).await;
+        let (status, body) = app.call(Method::GET, &uri, "").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        task = body;
+    }
+    assert_eq!(task["status"], json!("completed"), "{}", task);
+    assert!(task.get("finished").is_some());
+
+    let (_, stats) = app.call(Method::GET, "/docs/_stats", "").await;
+    assert_eq!(stats["segments"].as_array().unwrap().len(), 1);
+    assert_eq!(app.count("docs", "*").await, json!(3));
+
+    let (_, tasks) = app.call(Method::GET, "/_tasks", "").await;
+    assert_eq!(tasks.as_array().unwrap().len(), 1);
+    let (status, body) = app.call(Method::GET, "/_tasks/1000", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+}
+
+#[actix_rt::test]
+async fn test_analyze() {
+    let app = TestApp::new(&["docs"]);
+    app.create_index("docs").await;
+    let tokens = |body: &Value| {
+        body["tokens"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|token| (token["text"].as_str().unwrap().to_string(), token["position"].as_u64().unwrap()))
+            .collect::<Vec<_>>()
+    };
+
+    let req = json!({ "text": "The Quick fox", "analyzer": "default" });
+    let (status, body) = app.call_json(Method::POST, "/docs/_analyze", req).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(
+        tokens(&body),
+        vec![("the".to_string(), 0), ("quick".to_string(), 1), ("fox".to_string(), 2)]
+    );
+
+    let req = json!({
+        "text": "The Quick fox",
+        "analyzer": {
+            "name": "inline",
+            "tokenizer": { "type": "whitespace" },
+            "token_filters": [{ "type": "lowercase" }, { "type": "stop", "lang": "English" }]
+        }
+    });
+    let (status, body) = app.call_json(Method::POST, "/docs/_analyze", req).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(tokens(&body), vec![("quick".to_string(), 1), ("fox".to_string(), 2)]);
+    assert_eq!(body["tokens"][0]["offset_from"], json!(4));
+
+    let req = json!({ "text": "fox", "analyzer": "missing" });
+    let (status, body) = app.call_json(Method::POST, "/docs/_analyze", req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+
+    let req = json!({
+        "text": "fox",
+        "analyzer": {
+            "name": "inline",
+            "tokenizer": { "type": "simple" },
+            "search_token_filters": [{ "type": "synonym", "synonyms_path": "../users.json" }]
+        }
+    });
+    let (status, body) = app.call_json(Method::POST, "/docs/_analyze", req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+}
+
+#[actix_rt::test]
+async fn test_query_string_search_analyzer() {
+    let app = TestApp::new(&["shop"]);
+    let config = r#"{
+        "options": { "default_search_fields": [{ "field": "text" }] },
+        "analyzers": [{
+            "name": "synonyms",
+            "tokenizer": { "type": "simple" },
+            "token_filters": [{ "type": "lowercase" }],
+            "search_token_filters": [
+                { "type": "synonym", "synonyms": ["laptop, notebook"] },
+                { "type": "stop", "words": ["cheap"] }
+            ]
+        }],
+        "schema": [{
+            "name": "text",
+            "type": "text",
+            "options": {
+                "indexing": { "record": "position", "tokenizer": "synonyms" },
+                "stored": true
+            }
+        }]
+    }"#;
+    app.create_index_with("shop", config).await;
+    let docs = [
+        r#"{"index": {"text": "Cheap notebook"}}"#,
+        r#"{"index": {"text": "Gaming laptop"}}"#,
+    ];
+    let (status, body) = app.call(Method::POST, "/shop/_bulk", docs.join("\n")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    assert_eq!(app.count("shop", "laptop").await, json!(2));
+    assert_eq!(app.count("shop", "text:Notebook").await, json!(2));
+    assert_eq!(app.count("shop", "\"gaming notebook\"").await, json!(1));
+    // the stop words of the search analyzer are left out of the query
+    assert_eq!(app.count("shop", "cheap AND gaming").await, json!(1));
+}
+
+#[actix_rt::test]
+async fn test_synonyms_files() {
+    let app = TestApp::new(&["shop"]);
+    let synonyms_dir = app.data_dir.path().join("synonyms");
+    std::fs::create_dir(&synonyms_dir).unwrap();
+    std::fs::write(synonyms_dir.join("index.txt"), "# one way\ntv => television\n").unwrap();
+    std::fs::write(synonyms_dir.join("search.txt"), "laptop, notebook\n").unwrap();
+    let config = r#"{
+        "options": { "default_search_fields": [{ "field": "text" }] },
+        "analyzers": [{
+            "name": "synonyms",
+            "tokenizer": { "type": "simple" },
+            "token_filters": [{ "type": "synonym", "synonyms_path": "index.txt" }],
+            "search_token_filters": [{ "type": "synonym", "synonyms_path": "search.txt" }]
+        }],
+        "schema": [{
+            "name": "text",
+            "type": "text",
+            "options": {
+                "indexing": { "record": "position", "tokenizer": "synonyms" },
+                "stored": true
+            }
+        }]
+    }"#;
+    app.create_index_with("shop", config).await;
+
+    // the index analyzer keeps the rules of its file, the search one reads it again
+    let analyzers = std::fs::read(app.data_dir.path().join("shop").join("analyzers.json")).unwrap();
+    let analyzers: Value = serde_json::from_slice(&analyzers).unwrap();
+    assert_eq!(
+        analyzers[0]["token_filters"],
+        json!([{ "type": "synonym", "synonyms": ["tv => television"] }])
+    );
+    assert_eq!(
+        analyzers[0]["search_token_filters"],
+        json!([{ "type": "synonym", "synonyms_path": "search.txt" }])
+    );
+
+    let docs = [r#"{"index": {"text": "cheap tv"}}"#, r#"{"index": {"text": "gaming notebook"}}"#];
+    let (status, body) = app.call(Method::POST, "/shop/_bulk", docs.join("\n")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(app.count("shop", "television").await, json!(1));
+    assert_eq!(app.count("shop", "laptop").await, json!(1));
+
+    // a failed reload keeps the previous search analyzers
+    std::fs::remove_file(synonyms_dir.join("search.txt")).unwrap();
+    let (status, body) = app.call(Method::POST, "/shop/_reload_search_analyzers", "").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    assert_eq!(app.count("shop", "laptop").await, json!(1));
+
+    let (status, body) = app.call(Method::POST, "/synonyms", r#"{ "schema": [] }"#).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+}
+
+#[actix_rt::test]
+async fn test_stalled_exports() {
+    let app = TestApp::new(&["docs"]);
+    app.create_index("docs").await;
+    let text = "word ".repeat(60);
+    let docs = (0..3000)
+        .map(|id| json!({ "index": { "id": id, "text": text } }).to_string())
+        .collect::<Vec<_>>();
+    let (status, body) = app.call(Method::POST, "/docs/_bulk", docs.join("\n")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, body) = app.call(Method::POST, "/docs/_pit?keep_alive=60", "").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let pit = body["id"].as_str().unwrap().to_string();
+
+    // clients which don't read their exports
+    let index = app.state.indices.index("docs").await.unwrap();
+    let export = || ExportReq { query: None, pit: Some(pit.clone()) };
+    let mut stalled = Vec::new();
+    for _ in 0..4 {
+        stalled.push(index.export(export()).await.unwrap());
+    }
+    assert!(index.export(export()).await.is_err());
+
+    // searches of the point in time don't wait for the exports
+    let req = json!({ "query": "*", "pit": pit, "total_hits": true });
+    let (status, body) = app.call_json(Method::POST, "/docs/_search", req).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["total_hits"], json!(3000));
+
+    // the exports end with their clients
+    drop(stalled);
+    actix_rt::time::delay_for(std::time::Duration::from_millis(200)).await;
+    let chunks = index.export(export()).await.unwrap().collect::<Vec<_>>().await;
+    let lines = chunks
+        .into_iter()
+        .map(|chunk| chunk.unwrap().iter().filter(|b| **b == b'\n').count())
+        .sum::<usize>();
+    assert_eq!(lines, 3000);
+}
@@ -0,0 +1,203 @@
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use tantivy::Opstamp;
+
+use crate::config::CommitPolicy;
+use crate::index::LocalIndex;
+
+const DEFAULT_COMMIT_INTERVAL_MS: u64 = 1000;
+
+#[derive(Default)]
+struct State {
+    /// number of operations since the last commit
+    uncommitted: u64,
+    /// every operation with an opstamp below this one is searchable
+    searchable_opstamp: Opstamp,
+    /// number of failed commits and the error of the last one
+    failed_commits: u64,
+    last_error: String,
+    stopped: bool,
+}
+
+/// Decides when the background thread commits an index and lets writers
+/// wait until their operations become searchable.
+pub struct CommitScheduler {
+    interval: Option<Duration>,
+    max_uncommitted_docs: Option<u64>,
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+impl CommitScheduler {
+    pub fn new(policy: &CommitPolicy) -> Self {
+        let interval_ms = policy.interval_ms.unwrap_or(DEFAULT_COMMIT_INTERVAL_MS);
+        Self {
+            interval: Some(Duration::from_millis(interval_ms)).filter(|i| !i.is_zero()),
+            max_uncommitted_docs: policy.max_uncommitted_docs,
+            state: Mutex::default(),
+            cond: Condvar::new(),
+        }
+    }
+
+    /// Starts the thread committing `index` according to the policy.
+    /// The thread exits after `stop` is called or the index is dropped.
+    pub fn spawn(self: &Arc<Self>, index: Weak<LocalIndex>) -> crate::Result<()> {
+        let scheduler = self.clone();
+        std::thread::Builder::new()
+            .name("commit-scheduler".to_string())
+            .spawn(move || {
+                while scheduler.wait_next_commit() {
+                    let index = match index.upgrade() {
+                        Some(index) => index,
+                        None => break,
+                    };
+                    log::debug!("Scheduled commit");
+                    if let Err(err) = index.commit_sync() {
+                        log::error!("Scheduled commit failed: {}", err);
+                    }
+                }
+            })?;
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.stopped = true;
+        }
+        self.cond.notify_all();
+    }
+
+    pub fn has_interval(&self) -> bool {
+        self.interval.is_some()
+    }
+
+    pub fn record_ops(&self, count: u64) -> crate::Result<()> {
+        let mut state = self.state.lock().map_err(crate::error::lock_poisoned)?;
+        state.uncommitted += count;
+        if self.threshold_reached(&state) {
+            self.cond.notify_all();
+        }
+        Ok(())
+    }
+
+    /// Returns the number of uncommitted operations to pass to `committed`
+    pub fn pending(&self) -> crate::Result<u64> {
+        let state = self.state.lock().map_err(crate::error::lock_poisoned)?;
+        Ok(state.uncommitted)
+    }
+
+    pub fn committed(&self, opstamp: Opstamp, pending: u64) -> crate::Result<()> {
+        let mut state = self.state.lock().map_err(crate::error::lock_poisoned)?;
+        state.uncommitted = state.uncommitted.saturating_sub(pending);
+        state.searchable_opstamp = state.searchable_opstamp.max(opstamp);
+        self.cond.notify_all();
+        Ok(())
+    }
+
+    /// Wakes the waiters up with the error, rather than letting them wait for
+    /// commits which may keep failing
+    pub fn commit_failed(&self, err: &crate::Error) -> crate::Result<()> {
+        let mut state = self.state.lock().map_err(crate::error::lock_poisoned)?;
+        state.failed_commits += 1;
+        state.last_error = err.to_string();
+        self.cond.notify_all();
+        Ok(())
+    }
+
+    /// Blocks until the operation with `opstamp` is searchable, fails if a
+    /// commit fails or the scheduler stops before
+    pub fn wait_searchable(&self, opstamp: Opstamp) -> crate::Result<()> {
+        let mut state = self.state.lock().map_err(crate::error::lock_poisoned)?;
+        let failed_commits = state.failed_commits;
+        while state.searchable_opstamp <= opstamp {
+            if state.stopped {
+                return Err(crate::error::commit_scheduler_stopped());
+            }
+            if state.failed_commits != failed_commits {
+                return Err(crate::error::commit_failed(state.last_error.clone()));
+            }
+            state = self.cond.wait(state).map_err(crate::error::lock_poisoned)?;
+        }
+        Ok(())
+    }
+
+    fn threshold_reached(&self, state: &State) -> bool {
+        self.max_uncommitted_docs
+            .map(|max| state.uncommitted >= max)
+            .unwrap_or(false)
+    }
+
+    /// Blocks until the next commit is due, returns false once stopped
+    fn wait_next_commit(&self) -> bool {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return false,
+        };
+        let mut deadline = self.interval.map(|interval| Instant::now() + interval);
+        loop {
+            if state.stopped {
+                return false;
+            }
+            if self.threshold_reached(&state) {
+                return true;
+            }
+            let now = Instant::now();
+            state = match deadline {
+                Some(at) if at <= now => {
+                    if state.uncommitted > 0 {
+                        return true;
+                    }
+                    deadline = self.interval.map(|interval| now + interval);
+                    continue;
+                }
+                Some(at) => match self.cond.wait_timeout(state, at - now) {
+                    Ok((state, _)) => state,
+                    Err(_) => return false,
+                },
+                None => match self.cond.wait(state) {
+                    Ok(state) => state,
+                    Err(_) => return false,
+                },
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_wait_searchable() {
+        let scheduler = CommitScheduler::new(&CommitPolicy::default());
+        scheduler.committed(5, 0).unwrap();
+        assert!(scheduler.wait_searchable(4).is_ok());
+        scheduler.stop();
+        assert!(scheduler.wait_searchable(4).is_ok());
+        assert!(scheduler.wait_searchable(5).is_err());
+    }
+
+    #[test]
+    fn test_wait_searchable_commit_failed() {
+        let scheduler = Arc::new(CommitScheduler::new(&CommitPolicy::default()));
+        let done = Arc::new(AtomicBool::new(false));
+        let failing = {
+            let scheduler = scheduler.clone();
+            let done = done.clone();
+            // fails until the waiter returns, which may start waiting after the first failure
+            std::thread::spawn(move || {
+                while !done.load(Ordering::SeqCst) {
+                    scheduler.commit_failed(&crate::error::invalid_query("disk full".to_string())).unwrap();
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+            })
+        };
+        let waited = scheduler.wait_searchable(0);
+        done.store(true, Ordering::SeqCst);
+        failing.join().unwrap();
+        assert!(waited.unwrap_err().to_string().contains("disk full"));
+    }
+}
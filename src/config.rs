@@ -1,10 +1,10 @@
 use std::path::{PathBuf};
 use std::net::{SocketAddr};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use config::{Config, ConfigError, File, Environment};
 
-const APP_NAME: &'static str = "search";
+const APP_NAME: &str = "search";
 
 #[derive(Debug, Deserialize)]
 pub struct Api {
@@ -15,12 +15,41 @@ pub struct Api {
 //     std::cmp::min(num_cpu::get(), 8)
 // }
 
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CommitPolicy {
+    /// interval of background commits in milliseconds, 0 disables them
+    #[serde(default)]
+    pub interval_ms: Option<u64>,
+    /// commit as soon as this many operations are uncommitted
+    #[serde(default)]
+    pub max_uncommitted_docs: Option<u64>,
+}
+
+impl CommitPolicy {
+    /// Fills the missing values from `defaults`
+    pub fn or(&self, defaults: &CommitPolicy) -> CommitPolicy {
+        CommitPolicy {
+            interval_ms: self.interval_ms.or(defaults.interval_ms),
+            max_uncommitted_docs: self.max_uncommitted_docs.or(defaults.max_uncommitted_docs),
+        }
+    }
+}
+
+fn default_max_buckets() -> usize {
+    65_536
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Search {
     pub data_dir: PathBuf,
     #[serde(default)]
     pub indexer_num_threads: Option<usize>,
     pub indexer_heap_size: usize,
+    #[serde(default)]
+    pub commit: CommitPolicy,
+    /// buckets above which a search fails instead of aggregating
+    #[serde(default = "default_max_buckets")]
+    pub max_buckets: usize,
 }
 
 #[derive(Debug, Deserialize)]
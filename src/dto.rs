@@ -1,10 +1,41 @@
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
+use tantivy::query::Explanation;
 use tantivy::schema::NamedFieldDocument;
+use tantivy::tokenizer::Token;
+
+use crate::aggregation::{AggsReq, AggsResp};
+use crate::fast_field::BucketKey;
+use crate::index_config::{AnalyzerConfig, Operator, SearchField};
+use crate::index_registry::IndexEntry;
+use crate::query::{DslValue, QueryDsl};
+use crate::sort::SortField;
+use crate::source::SourceReq;
+
+/// When a write should become searchable
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Refresh {
+    /// leave it to the background commit
+    #[default]
+    False,
+    /// block until a commit makes the write searchable
+    WaitFor,
+}
 
 pub struct AddDocReq {
     pub doc: String,
     pub commit: bool,
+    pub refresh: Refresh,
+}
+
+pub struct UpdateDocReq {
+    pub id: String,
+    pub doc: String,
+    pub commit: bool,
+    pub refresh: Refresh,
 }
 
 #[derive(Deserialize)]
@@ -12,13 +43,208 @@ pub struct DeleteByTermReq {
     pub field: String,
     pub term: String,
     pub commit: bool,
+    #[serde(default)]
+    pub refresh: Refresh,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteByQueryReq {
+    pub query: SearchQuery,
+    #[serde(default)]
+    pub default_fields: Option<Vec<SearchField>>,
+    #[serde(default)]
+    pub default_operator: Option<Operator>,
+    #[serde(default)]
+    pub commit: bool,
+    #[serde(default)]
+    pub refresh: Refresh,
+}
+
+#[derive(Serialize)]
+pub struct DeleteByQueryResp {
+    pub deleted: u64,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteTerm {
+    pub field: String,
+    pub term: String,
+}
+
+/// Single line of a bulk request
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkAction {
+    Index(serde_json::Value),
+    Update(serde_json::Value),
+    Delete(DeleteTerm),
+}
+
+#[derive(Serialize)]
+pub struct BulkItemResult {
+    pub line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Default, Serialize)]
+pub struct BulkResp {
+    pub errors: bool,
+    pub items: Vec<BulkItemResult>,
+}
+
+/// Either a query parser string or a structured query
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum SearchQuery {
+    Parser(String),
+    Dsl(QueryDsl),
+}
+
+fn default_limit() -> usize {
+    10
+}
+
+fn default_fragment_length() -> usize {
+    150
+}
+
+fn default_pre_tag() -> String {
+    "<em>".to_string()
+}
+
+fn default_post_tag() -> String {
+    "</em>".to_string()
+}
+
+#[derive(Clone, Deserialize)]
+pub struct HighlightReq {
+    pub fields: Vec<String>,
+    /// max length of a fragment in chars
+    #[serde(default = "default_fragment_length")]
+    pub max_fragment_length: usize,
+    #[serde(default = "default_pre_tag")]
+    pub pre_tag: String,
+    #[serde(default = "default_post_tag")]
+    pub post_tag: String,
 }
 
 #[derive(Deserialize)]
 pub struct SearchReq {
-    pub query: String,
+    pub query: SearchQuery,
+    /// overrides the index default search fields
+    #[serde(default)]
+    pub default_fields: Option<Vec<SearchField>>,
+    /// overrides the index default operator
+    #[serde(default)]
+    pub default_operator: Option<Operator>,
+    #[serde(default = "default_limit")]
     pub limit: usize,
+    #[serde(default)]
     pub offset: usize,
+    #[serde(default)]
+    pub highlight: Option<HighlightReq>,
+    #[serde(default)]
+    pub aggs: Option<AggsReq>,
+    /// sorts by relevance when missing
+    #[serde(default)]
+    pub sort: Option<Vec<SortField>>,
+    /// `cursor` of the last document of the previous page. Ties are broken by
+    /// the document address, which commits and merges change, so pages are only
+    /// consistent when searching a point in time.
+    #[serde(default)]
+    pub search_after: Option<String>,
+    /// id of the point in time to search instead of the latest index generation
+    #[serde(default)]
+    pub pit: Option<String>,
+    /// counts every matching document, not only the returned ones
+    #[serde(default)]
+    pub total_hits: bool,
+    /// returns how the score of each hit was computed
+    #[serde(default)]
+    pub explain: bool,
+    /// stored fields returned in the hits, every one when missing
+    #[serde(default, rename = "_source")]
+    pub source: Option<SourceReq>,
+}
+
+#[derive(Deserialize)]
+pub struct DocKey {
+    pub field: String,
+    pub value: DslValue,
+}
+
+#[derive(Deserialize)]
+pub struct MultiGetReq {
+    pub docs: Vec<DocKey>,
+}
+
+#[derive(Serialize)]
+pub struct MultiGetItem {
+    pub found: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<NamedFieldDocument>,
+}
+
+/// Documents in the order of the requested keys
+#[derive(Serialize)]
+pub struct MultiGetResp {
+    pub docs: Vec<MultiGetItem>,
+}
+
+#[derive(Deserialize)]
+pub struct CountReq {
+    /// counts every document when missing
+    #[serde(default)]
+    pub query: Option<SearchQuery>,
+    #[serde(default)]
+    pub default_fields: Option<Vec<SearchField>>,
+    #[serde(default)]
+    pub default_operator: Option<Operator>,
+    #[serde(default)]
+    pub pit: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ExplainReq {
+    pub query: SearchQuery,
+    #[serde(default)]
+    pub default_fields: Option<Vec<SearchField>>,
+    #[serde(default)]
+    pub default_operator: Option<Operator>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum AnalyzerReq {
+    /// analyzer registered in the index, custom or built-in
+    Name(String),
+    /// analyzer built for the request only
+    Config(AnalyzerConfig),
+}
+
+#[derive(Deserialize)]
+pub struct AnalyzeReq {
+    pub text: String,
+    pub analyzer: AnalyzerReq,
+    /// analyzes the text as a query, with the search token filters
+    #[serde(default)]
+    pub search: bool,
+}
+
+#[derive(Serialize)]
+pub struct AnalyzeResp {
+    pub tokens: Vec<Token>,
+}
+
+#[derive(Deserialize)]
+pub struct ExportReq {
+    /// exports every document when missing
+    #[serde(default)]
+    pub query: Option<SearchQuery>,
+    /// id of the point in time to export instead of the latest index generation
+    #[serde(default)]
+    pub pit: Option<String>,
 }
 
 pub type Score = f32;
@@ -27,9 +253,118 @@ pub type Score = f32;
 pub struct ScoredDocument<D = NamedFieldDocument> {
     pub score: Score,
     pub doc: D,
+    /// highlighted fragments by field name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlights: Option<BTreeMap<String, String>>,
+    /// values of the sort fields, null for a missing value
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<Vec<Option<BucketKey>>>,
+    /// position of the document for `search_after`,
+    /// returned when sorting or searching with a cursor or a point in time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    /// tree of the score computation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<Explanation>,
+    /// index of the document when searching several indices
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct SearchResp {
-    pub docs: Vec<ScoredDocument>
-}
\ No newline at end of file
+    pub docs: Vec<ScoredDocument>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_hits: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aggs: Option<AggsResp>,
+    /// indices matched by an alias or a wildcard and skipped as they failed to open
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_indices: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct CountResp {
+    pub count: usize,
+    /// like in `SearchResp`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_indices: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct PointInTimeResp {
+    pub id: String,
+}
+
+#[derive(Serialize)]
+pub struct ClosePointInTimeResp {
+    /// false if the point in time was not found or had expired
+    pub closed: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexStatus {
+    Open,
+    /// corrupt or not completely created
+    Failed,
+}
+
+#[derive(Serialize)]
+pub struct IndexInfo {
+    pub name: String,
+    #[serde(flatten)]
+    pub entry: IndexEntry,
+    pub status: IndexStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Default, Serialize)]
+pub struct StatsTotals {
+    pub num_docs: u64,
+    pub num_deleted_docs: u64,
+    pub num_segments: usize,
+    pub size_in_bytes: u64,
+    /// on disk size by file extension
+    pub files: BTreeMap<String, u64>,
+}
+
+impl StatsTotals {
+    pub fn add(&mut self, other: &StatsTotals) {
+        self.num_docs += other.num_docs;
+        self.num_deleted_docs += other.num_deleted_docs;
+        self.num_segments += other.num_segments;
+        self.size_in_bytes += other.size_in_bytes;
+        for (extension, size) in &other.files {
+            *self.files.entry(extension.clone()).or_default() += size;
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SegmentStats {
+    pub id: String,
+    pub max_doc: u32,
+    pub num_docs: u32,
+    pub num_deleted_docs: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delete_opstamp: Option<u64>,
+    pub size_in_bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct IndexStats {
+    #[serde(flatten)]
+    pub totals: StatsTotals,
+    /// opstamp of the last commit
+    pub opstamp: u64,
+    pub segments: Vec<SegmentStats>,
+}
+
+#[derive(Default, Serialize)]
+pub struct AllStats {
+    pub total: StatsTotals,
+    /// stats of the open indices by name
+    pub indices: BTreeMap<String, IndexStats>,
+}
@@ -5,6 +5,7 @@ use anyhow::anyhow;
 use actix_web::{
     HttpResponse,
     ResponseError,
+    error::BlockingError,
     http::StatusCode
 };
 use serde_json::json;
@@ -35,14 +36,75 @@ impl Error {
             err
         }
     }
+    fn too_many_requests(err: anyhow::Error) -> Self {
+        Self {
+            status_code: StatusCode::TOO_MANY_REQUESTS,
+            err
+        }
+    }
 }
 
+/// Unwraps an error returned from a `web::block` closure keeping its status code
+pub fn blocking(err: BlockingError<Error>) -> Error {
+    match err {
+        BlockingError::Error(err) => err,
+        BlockingError::Canceled => Error::internal(anyhow!("Blocking operation canceled")),
+    }
+}
 pub fn lock_poisoned<Guard>(_err: std::sync::PoisonError<Guard>) -> Error {
     Error::internal(anyhow!("Lock poisoned"))
 }
+pub fn commit_scheduler_stopped() -> Error {
+    Error::internal(anyhow!("Commit scheduler stopped"))
+}
+pub fn commit_failed(reason: String) -> Error {
+    Error::internal(anyhow!("Commit failed: {0}", reason))
+}
 pub fn index_not_exist(index: String) -> Error {
     Error::not_found(anyhow!("Index '{0}' not exist", index))
 }
+pub fn index_unavailable(index: String, reason: String) -> Error {
+    Error::internal(anyhow!("Index '{0}' is unavailable: {1}", index, reason))
+}
+pub fn alias_not_exist(alias: String, index: String) -> Error {
+    Error::not_found(anyhow!("Alias '{0}' of index '{1}' not exist", alias, index))
+}
+pub fn alias_without_write_index(alias: String) -> Error {
+    Error::bad_request(anyhow!("Alias '{0}' has several indices and no write index", alias))
+}
+pub fn alias_with_several_indices(alias: String) -> Error {
+    Error::bad_request(anyhow!("Alias '{0}' has several indices, only searches and counts read all of them", alias))
+}
+pub fn name_already_used(name: String) -> Error {
+    Error::bad_request(anyhow!("'{0}' is already the name of an index or an alias", name))
+}
+pub fn index_being_created(index: String) -> Error {
+    Error::bad_request(anyhow!("Index '{0}' is being created", index))
+}
+pub fn document_not_found() -> Error {
+    Error::not_found(anyhow!("Document not found"))
+}
+pub fn document_not_matching() -> Error {
+    Error::not_found(anyhow!("Document does not match the query"))
+}
+pub fn point_in_time_not_found(id: String) -> Error {
+    Error::not_found(anyhow!("Point in time '{0}' not found or expired", id))
+}
+pub fn too_many_point_in_times(max: usize) -> Error {
+    Error::bad_request(anyhow!("Index has already {0} open points in time", max))
+}
+pub fn too_many_exports(max: usize) -> Error {
+    Error::too_many_requests(anyhow!("Index has already {0} running exports", max))
+}
+pub fn export_timed_out() -> Error {
+    Error::internal(anyhow!("Export client did not receive the documents in time"))
+}
+pub fn too_many_expansions(max: usize) -> Error {
+    Error::bad_request(anyhow!("Text expands to more than {0} phrases of alternative tokens, like synonyms", max))
+}
+pub fn analyzer_not_exist(name: String) -> Error {
+    Error::bad_request(anyhow!("Analyzer '{0}' not exist", name))
+}
 pub fn field_not_exist(field: String) -> Error {
     Error::bad_request(anyhow!("Field '{0}' not exist", field))
 }
@@ -52,6 +114,39 @@ pub fn value_parsing_err<E: Into<anyhow::Error>>(err: E) -> Error {
 pub fn invalid_index_name(name: String) -> Error {
     Error::bad_request(anyhow!(name))
 }
+pub fn field_not_fast(field: String) -> Error {
+    Error::bad_request(anyhow!("Field '{0}' is not a fast field", field))
+}
+pub fn field_not_indexed(field: String) -> Error {
+    Error::bad_request(anyhow!("Field '{0}' is not indexed", field))
+}
+pub fn invalid_query(msg: String) -> Error {
+    Error::bad_request(anyhow!(msg))
+}
+pub fn invalid_settings(msg: String) -> Error {
+    Error::bad_request(anyhow!(msg))
+}
+pub fn task_not_found(id: u64) -> Error {
+    Error::not_found(anyhow!("Task '{0}' not found", id))
+}
+pub fn primary_key_not_defined() -> Error {
+    Error::bad_request(anyhow!("Index has no primary key"))
+}
+pub fn primary_key_missing(field: String) -> Error {
+    Error::bad_request(anyhow!("Document has no primary key field '{0}'", field))
+}
+pub fn primary_key_not_exact(field: String) -> Error {
+    Error::bad_request(anyhow!("Primary key field '{0}' must be a raw text field or a fast numeric or date field", field))
+}
+pub fn primary_key_not_stored(field: String) -> Error {
+    Error::bad_request(anyhow!("Primary key field '{0}' is not stored", field))
+}
+pub fn primary_key_shared(key: String) -> Error {
+    Error::bad_request(anyhow!("Documents not matching the query have the primary key {0} too", key))
+}
+pub fn primary_key_mismatch(id: String) -> Error {
+    Error::bad_request(anyhow!("Document primary key doesn't match id '{0}'", id))
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -0,0 +1,178 @@
+use serde::ser::Error as _;
+use serde::{Serialize, Serializer};
+use tantivy::chrono::{DateTime, NaiveDateTime, Utc};
+use tantivy::fastfield::{
+    DynamicFastFieldReader, FacetReader, FastFieldReader, MultiValuedFastFieldReader,
+};
+use tantivy::schema::{Cardinality, Facet, Field, FieldType, Schema};
+use tantivy::{u64_to_f64, u64_to_i64, DocId, SegmentReader};
+
+/// Type of the values stored in a fast field
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FastType {
+    U64,
+    I64,
+    F64,
+    Date,
+    Facet,
+}
+
+/// Fast field which aggregations and sorting read their values from
+#[derive(Debug, Clone, Copy)]
+pub struct FastField {
+    field: Field,
+    fast_type: FastType,
+    cardinality: Cardinality,
+}
+
+impl FastField {
+    pub fn new(schema: &Schema, field_name: &str) -> crate::Result<Self> {
+        let field = schema
+            .get_field(field_name)
+            .ok_or_else(|| crate::error::field_not_exist(field_name.to_string()))?;
+        let (fast_type, cardinality) = match schema.get_field_entry(field).field_type() {
+            FieldType::U64(options) => (FastType::U64, options.get_fastfield_cardinality()),
+            FieldType::I64(options) => (FastType::I64, options.get_fastfield_cardinality()),
+            FieldType::F64(options) => (FastType::F64, options.get_fastfield_cardinality()),
+            FieldType::Date(options) => (FastType::Date, options.get_fastfield_cardinality()),
+            FieldType::HierarchicalFacet(_) => (FastType::Facet, Some(Cardinality::MultiValues)),
+            _ => (FastType::U64, None),
+        };
+        let cardinality =
+            cardinality.ok_or_else(|| crate::error::field_not_fast(field_name.to_string()))?;
+        Ok(Self {
+            field,
+            fast_type,
+            cardinality,
+        })
+    }
+
+    /// Like `new` but only accepts numeric and date fields
+    pub fn numeric(schema: &Schema, field_name: &str) -> crate::Result<Self> {
+        let field = Self::new(schema, field_name)?;
+        if field.fast_type == FastType::Facet {
+            return Err(crate::error::invalid_query(format!(
+                "Field '{}' is not numeric",
+                field_name
+            )));
+        }
+        Ok(field)
+    }
+
+    pub fn fast_type(&self) -> FastType {
+        self.fast_type
+    }
+
+    pub fn reader(&self, segment: &SegmentReader) -> tantivy::Result<FastValues> {
+        let fast_fields = segment.fast_fields();
+        Ok(match (self.fast_type, self.cardinality) {
+            (FastType::Facet, _) => FastValues::Facet(segment.facet_reader(self.field)?),
+            (_, Cardinality::SingleValue) => {
+                FastValues::Single(fast_fields.u64_lenient(self.field)?)
+            }
+            (_, Cardinality::MultiValues) => {
+                FastValues::Multi(fast_fields.u64s_lenient(self.field)?)
+            }
+        })
+    }
+
+    /// Converts a raw fast field value into a bucket key.
+    /// Facet ordinals must be resolved through `FastValues::facet` instead.
+    pub fn key(&self, raw: u64) -> BucketKey {
+        match self.fast_type {
+            FastType::U64 | FastType::Facet => BucketKey::U64(raw),
+            FastType::I64 => BucketKey::I64(u64_to_i64(raw)),
+            FastType::F64 => BucketKey::F64(raw),
+            FastType::Date => BucketKey::Date(u64_to_i64(raw)),
+        }
+    }
+
+    /// Converts a raw fast field value into a number, dates are in seconds
+    pub fn number(&self, raw: u64) -> f64 {
+        match self.fast_type {
+            FastType::U64 | FastType::Facet => raw as f64,
+            FastType::I64 | FastType::Date => u64_to_i64(raw) as f64,
+            FastType::F64 => u64_to_f64(raw),
+        }
+    }
+}
+
+/// Segment level reader returning the raw `u64` representation of the values
+pub enum FastValues {
+    Single(DynamicFastFieldReader<u64>),
+    Multi(MultiValuedFastFieldReader<u64>),
+    Facet(FacetReader),
+}
+
+impl FastValues {
+    pub fn values(&self, doc: DocId, output: &mut Vec<u64>) {
+        output.clear();
+        match self {
+            FastValues::Single(reader) => output.push(reader.get(doc)),
+            FastValues::Multi(reader) => reader.get_vals(doc, output),
+            FastValues::Facet(reader) => reader.facet_ords(doc, output),
+        }
+    }
+
+    pub fn facet(&mut self, ord: u64) -> tantivy::Result<Option<Facet>> {
+        match self {
+            FastValues::Facet(reader) => {
+                let mut facet = Facet::root();
+                reader.facet_from_ord(ord, &mut facet)?;
+                Ok(Some(facet))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Segment level reader converting the values of a numeric field into `f64`
+pub struct NumericValues {
+    field: FastField,
+    values: FastValues,
+    buf: Vec<u64>,
+}
+
+impl NumericValues {
+    pub fn new(field: FastField, segment: &SegmentReader) -> tantivy::Result<Self> {
+        Ok(Self {
+            field,
+            values: field.reader(segment)?,
+            buf: Vec::new(),
+        })
+    }
+
+    pub fn values(&mut self, doc: DocId) -> impl Iterator<Item = f64> + '_ {
+        self.values.values(doc, &mut self.buf);
+        let field = self.field;
+        self.buf.iter().map(move |raw| field.number(*raw))
+    }
+}
+
+/// Key of an aggregation bucket
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BucketKey {
+    U64(u64),
+    I64(i64),
+    /// order preserving `u64` representation of `f64`
+    F64(u64),
+    /// timestamp in seconds
+    Date(i64),
+    Str(String),
+}
+
+impl Serialize for BucketKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            BucketKey::U64(val) => serializer.serialize_u64(*val),
+            BucketKey::I64(val) => serializer.serialize_i64(*val),
+            BucketKey::F64(val) => serializer.serialize_f64(u64_to_f64(*val)),
+            BucketKey::Date(val) => {
+                let date = NaiveDateTime::from_timestamp_opt(*val, 0)
+                    .ok_or_else(|| S::Error::custom(format!("Timestamp {} is out of range", val)))?;
+                serializer.serialize_str(&DateTime::<Utc>::from_utc(date, Utc).to_rfc3339())
+            }
+            BucketKey::Str(val) => serializer.serialize_str(val),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use tantivy::query::Query;
+use tantivy::schema::{Document, FieldType, Schema};
+use tantivy::{Searcher, Snippet, SnippetGenerator};
+
+use crate::dto::HighlightReq;
+
+/// Builds highlighted fragments of the requested fields for search hits
+pub struct Highlighter {
+    generators: Vec<(String, SnippetGenerator)>,
+    pre_tag: String,
+    post_tag: String,
+}
+
+impl Highlighter {
+    pub fn new(
+        searcher: &Searcher,
+        schema: &Schema,
+        query: &dyn Query,
+        req: HighlightReq,
+    ) -> crate::Result<Self> {
+        let HighlightReq { fields, max_fragment_length, pre_tag, post_tag } = req;
+        let generators = fields
+            .into_iter()
+            .map(|field_name| {
+                let field = schema
+                    .get_field(&field_name)
+                    .ok_or_else(|| crate::error::field_not_exist(field_name.clone()))?;
+                let field_entry = schema.get_field_entry(field);
+                if !matches!(field_entry.field_type(), FieldType::Str(_)) || !field_entry.is_stored() {
+                    return Err(crate::error::invalid_query(format!(
+                        "Field '{}' must be a stored text field to be highlighted",
+                        field_name
+                    )));
+                }
+                let mut generator = SnippetGenerator::create(searcher, query, field)?;
+                generator.set_max_num_chars(max_fragment_length);
+                Ok((field_name, generator))
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+        Ok(Self {
+            generators,
+            pre_tag,
+            post_tag,
+        })
+    }
+
+    /// Returns fragments of the fields which have matches
+    pub fn highlight(&self, doc: &Document) -> BTreeMap<String, String> {
+        self.generators
+            .iter()
+            .map(|(field_name, generator)| (field_name, generator.snippet_from_doc(doc)))
+            .filter(|(_, snippet)| !snippet.highlighted().is_empty())
+            .map(|(field_name, snippet)| (field_name.clone(), self.mark(&snippet)))
+            .collect()
+    }
+
+    fn mark(&self, snippet: &Snippet) -> String {
+        mark(snippet.fragments(), snippet.highlighted(), &self.pre_tag, &self.post_tag)
+    }
+}
+
+/// Wraps the ranges of the fragment in the tags. The text is HTML escaped like
+/// `Snippet::to_html` does, so that it can't inject markup next to the tags.
+fn mark(fragment: &str, highlighted: &[Range<usize>], pre_tag: &str, post_tag: &str) -> String {
+    let mut marked = String::with_capacity(fragment.len());
+    let mut start = 0;
+    for range in highlighted {
+        marked.push_str(&htmlescape::encode_minimal(&fragment[start..range.start]));
+        marked.push_str(pre_tag);
+        marked.push_str(&htmlescape::encode_minimal(&fragment[range.clone()]));
+        marked.push_str(post_tag);
+        start = range.end;
+    }
+    marked.push_str(&htmlescape::encode_minimal(&fragment[start..]));
+    marked
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mark() {
+        let fragment = "<b>fish</b> & chips";
+        assert_eq!(
+            mark(fragment, &[3..7, 14..19], "<em>", "</em>"),
+            "&lt;b&gt;<em>fish</em>&lt;/b&gt; &amp; <em>chips</em>"
+        );
+        assert_eq!(mark("a < b", &[], "<em>", "</em>"), "a &lt; b");
+    }
+}
@@ -1,20 +1,101 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::RwLock;
 use std::sync::Arc;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use actix_web::web::block;
+use actix_web::web::{block, Bytes};
+use futures::channel::mpsc;
+use futures::executor::block_on;
 
+use tantivy::collector::{Count, MultiCollector, TopDocs};
+use tantivy::directory::error::OpenReadError;
+use tantivy::query::{AllQuery, BooleanQuery, Occur, Query, TermQuery, Weight};
+use tantivy::schema::{Document, Field, FieldType, IndexRecordOption, NamedFieldDocument, Schema, Term};
+use tantivy::tokenizer::TokenizerManager;
+use tantivy::{
+    Directory, DocSet, HasLen, LeasedItem, Opstamp, Searcher, SegmentId, SegmentMeta, SegmentReader,
+    TantivyError, UserOperation, TERMINATED,
+};
+
+use crate::aggregation::AggsHandles;
+use crate::commit_scheduler::CommitScheduler;
 use crate::config;
-use crate::index_config::{IndexConfig, Analyzers};
+use crate::fast_field::FastType;
+use crate::index_config::{IndexConfig, IndexOptions, Analyzers, Operator, SearchField};
 use crate::dto::*;
+use crate::highlight::Highlighter;
+use crate::merge_policy::MergePolicyConfig;
+use crate::point_in_time::PointInTimes;
+use crate::query::QueryStringParser;
+use crate::sort::{Sort, SortCollector, SortField, SortPosition};
+use crate::source::SourceFilter;
+use crate::utils::json_file_storage::JsonFileStorage;
 
-const ANALYZERS_FILE: &'static str = "analyzers.json";
+const ANALYZERS_FILE: &str = "analyzers.json";
+const OPTIONS_FILE: &str = "options.json";
+const MERGE_POLICY_FILE: &str = "merge_policy.json";
+/// size above which exported documents are sent to the client
+const EXPORT_CHUNK_SIZE: usize = 64 * 1024;
+/// number of chunks buffered for a slow client
+const EXPORT_BUFFERED_CHUNKS: usize = 4;
+/// keys of matching documents checked and deleted at once by a delete by query
+const DELETE_BY_QUERY_BATCH_SIZE: usize = 10_000;
+/// exports running at once on an index, each one has a thread
+const MAX_CONCURRENT_EXPORTS: usize = 4;
+/// time a client may take to receive a chunk before its export is aborted
+const EXPORT_SEND_TIMEOUT: Duration = Duration::from_secs(60);
+/// interval of the checks whether a slow client received a chunk
+const EXPORT_SEND_POLL_INTERVAL: Duration = Duration::from_millis(10);
 
 pub struct LocalIndex {
     schema: tantivy::schema::Schema,
+    primary_key: Option<Field>,
+    default_search_fields: Vec<SearchField>,
+    default_operator: Operator,
     index: tantivy::Index,
     reader: tantivy::IndexReader,
     writer: RwLock<tantivy::IndexWriter>,
+    /// dir of the files analyzers read, like synonyms
+    data_dir: PathBuf,
+    analyzers: Analyzers,
+    /// analyzers of the queries, which may differ from the indexing ones
+    search_tokenizers: TokenizerManager,
+    merge_policy_storage: JsonFileStorage<MergePolicyConfig>,
+    merge_policy: RwLock<MergePolicyConfig>,
+    /// set while segments with too many deletes are rewritten
+    expunging_deletes: Arc<AtomicBool>,
+    commit_scheduler: Arc<CommitScheduler>,
+    point_in_times: Arc<PointInTimes>,
+    max_buckets: usize,
+    /// number of running exports
+    exports: Arc<AtomicUsize>,
+}
+
+/// Slot of a running export, released when dropped
+struct ExportPermit(Arc<AtomicUsize>);
+
+impl ExportPermit {
+    fn acquire(exports: &Arc<AtomicUsize>) -> crate::Result<Self> {
+        exports
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |running| {
+                if running < MAX_CONCURRENT_EXPORTS {
+                    Some(running + 1)
+                } else {
+                    None
+                }
+            })
+            .map_err(|_| crate::error::too_many_exports(MAX_CONCURRENT_EXPORTS))?;
+        Ok(Self(exports.clone()))
+    }
+}
+
+impl Drop for ExportPermit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 impl LocalIndex {
@@ -23,77 +104,209 @@ impl LocalIndex {
         path: &Path,
         index_conf: &IndexConfig,
         config: &config::Search
-    ) -> crate::Result<Self> {
+    ) -> crate::Result<Arc<Self>> {
+        let primary_key = Self::primary_key_field(&index_conf.schema, &index_conf.options)?;
+        // delete by query reads the keys of the matching documents
+        if let Some(field) = primary_key {
+            Self::primary_key_exact(&index_conf.schema, field)?;
+            Self::primary_key_stored(&index_conf.schema, field)?;
+        }
+        Self::search_fields(&index_conf.schema, &index_conf.options.default_search_fields)?;
+        index_conf.settings.validate()?;
+        let mut analyzers = index_conf.analyzers.clone();
+        for analyzer in &mut analyzers {
+            analyzer.inline_synonyms_files(&config.data_dir)?;
+        }
+        let search_tokenizers = TokenizerManager::default();
+        Self::add_search_analyzers(&search_tokenizers, &analyzers, &config.data_dir)?;
+
         let index = tantivy::Index::builder()
-            .settings(index_conf.settings.clone())
+            .settings(index_conf.settings.tantivy.clone())
             .schema(index_conf.schema.clone())
-            .create_in_dir(&path)?;
+            .create_in_dir(path)?;
 
         let analyzers_file = std::fs::File::create(path.join(ANALYZERS_FILE))?;
-        serde_json::to_writer(analyzers_file, &index_conf.analyzers)?;
+        serde_json::to_writer(analyzers_file, &analyzers)?;
 
-        Self::add_analyzers(&index, &index_conf.analyzers);
+        JsonFileStorage::new(path.join(OPTIONS_FILE)).store(&index_conf.options)?;
 
-        Self::from_tantivy_index(index, config)
+        let merge_policy_storage = JsonFileStorage::new(path.join(MERGE_POLICY_FILE));
+        merge_policy_storage.store(&index_conf.settings.merge_policy)?;
+
+        Self::add_analyzers(index.tokenizers(), &analyzers, &config.data_dir)?;
+
+        Self::from_tantivy_index(
+            index,
+            &index_conf.options,
+            analyzers,
+            search_tokenizers,
+            merge_policy_storage,
+            config,
+        )
     }
 
     pub fn open_in_dir(
         path: &Path,
         config: &config::Search
-    ) -> crate::Result<Self> {
+    ) -> crate::Result<Arc<Self>> {
         let index = tantivy::Index::open_in_dir(path)?;
 
         let analyzers_file = std::fs::File::open(path.join(ANALYZERS_FILE))?;
         let analyzers: Analyzers = serde_json::from_reader(analyzers_file)?;
 
-        Self::add_analyzers(&index, &analyzers);
+        let options: IndexOptions = JsonFileStorage::new(path.join(OPTIONS_FILE)).load()?;
 
-        Self::from_tantivy_index(index, config)
+        // indices created before the policy was configurable have no file and use the defaults
+        let merge_policy_storage = JsonFileStorage::new(path.join(MERGE_POLICY_FILE));
+
+        Self::add_analyzers(index.tokenizers(), &analyzers, &config.data_dir)?;
+
+        // a synonyms file of the search analyzers may be missing or broken, until it is
+        // fixed and the analyzers reloaded the queries are analyzed like the documents
+        let search_tokenizers = TokenizerManager::default();
+        if let Err(err) = Self::add_search_analyzers(&search_tokenizers, &analyzers, &config.data_dir) {
+            log::error!("Search analyzers of index {} not loaded: {}", path.display(), err);
+            Self::add_analyzers(&search_tokenizers, &analyzers, &config.data_dir)?;
+        }
+
+        Self::from_tantivy_index(index, &options, analyzers, search_tokenizers, merge_policy_storage, config)
     }
 
-    fn add_analyzers(index: &tantivy::Index, analyzers: &Analyzers) {
-        let tokenizers = index.tokenizers();
+    fn add_analyzers(tokenizers: &TokenizerManager, analyzers: &Analyzers, data_dir: &Path) -> crate::Result<()> {
         for analyzer in analyzers {
-            tokenizers.register(&analyzer.name, analyzer.make_analyzer())
+            tokenizers.register(&analyzer.name, analyzer.make_analyzer(data_dir)?)
         }
+        Ok(())
     }
 
-    fn from_tantivy_index(index: tantivy::Index, config: &config::Search) -> crate::Result<LocalIndex> {
+    /// Registers the search analyzers, none of them if one fails
+    fn add_search_analyzers(
+        tokenizers: &TokenizerManager,
+        analyzers: &Analyzers,
+        data_dir: &Path,
+    ) -> crate::Result<()> {
+        let search_analyzers = analyzers
+            .iter()
+            .map(|analyzer| Ok((&analyzer.name, analyzer.make_search_analyzer(data_dir)?)))
+            .collect::<crate::Result<Vec<_>>>()?;
+        for (name, analyzer) in search_analyzers {
+            tokenizers.register(name, analyzer);
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the search analyzers, e.g. after a synonyms file changed
+    pub fn reload_search_analyzers(&self) -> crate::Result<()> {
+        Self::add_search_analyzers(&self.search_tokenizers, &self.analyzers, &self.data_dir)
+    }
+
+    fn primary_key_field(schema: &Schema, options: &IndexOptions) -> crate::Result<Option<Field>> {
+        let field_name = match &options.primary_key {
+            Some(field_name) => field_name,
+            None => return Ok(None),
+        };
+        let field = schema
+            .get_field(field_name)
+            .ok_or_else(|| crate::error::field_not_exist(field_name.clone()))?;
+        if !schema.get_field_entry(field).is_indexed() {
+            return Err(crate::error::field_not_indexed(field_name.clone()));
+        }
+        Ok(Some(field))
+    }
+
+    /// Upserts delete the term of the key value, which only matches the indexed term when
+    /// the value isn't tokenized. Indices created before the check keep their key.
+    fn primary_key_exact(schema: &Schema, field: Field) -> crate::Result<()> {
+        let exact = match schema.get_field_entry(field).field_type() {
+            FieldType::Str(options) => options
+                .get_indexing_options()
+                .is_some_and(|indexing| indexing.tokenizer() == "raw"),
+            FieldType::U64(options)
+            | FieldType::I64(options)
+            | FieldType::F64(options)
+            | FieldType::Date(options) => options.is_fast(),
+            FieldType::HierarchicalFacet(_) | FieldType::Bytes(_) => false,
+        };
+        if !exact {
+            return Err(crate::error::primary_key_not_exact(schema.get_field_name(field).to_string()));
+        }
+        Ok(())
+    }
+
+    /// Indices created before the key had to be stored may still have an unstored one
+    fn primary_key_stored(schema: &Schema, field: Field) -> crate::Result<()> {
+        if !schema.get_field_entry(field).is_stored() {
+            return Err(crate::error::primary_key_not_stored(schema.get_field_name(field).to_string()));
+        }
+        Ok(())
+    }
+
+    fn search_fields(schema: &Schema, fields: &[SearchField]) -> crate::Result<Vec<(Field, Option<Score>)>> {
+        fields
+            .iter()
+            .map(|SearchField { field: field_name, boost }| {
+                let field = schema
+                    .get_field(field_name)
+                    .ok_or_else(|| crate::error::field_not_exist(field_name.clone()))?;
+                if !schema.get_field_entry(field).is_indexed() {
+                    return Err(crate::error::field_not_indexed(field_name.clone()));
+                }
+                Ok((field, *boost))
+            })
+            .collect()
+    }
+
+    fn from_tantivy_index(
+        index: tantivy::Index,
+        options: &IndexOptions,
+        analyzers: Analyzers,
+        search_tokenizers: TokenizerManager,
+        merge_policy_storage: JsonFileStorage<MergePolicyConfig>,
+        config: &config::Search
+    ) -> crate::Result<Arc<LocalIndex>> {
         let schema = index.schema();
+        let primary_key = Self::primary_key_field(&schema, options)?;
         let reader = index.reader()?;
         let writer = if let Some(num_threads) = config.indexer_num_threads {
             index.writer_with_num_threads(num_threads, config.indexer_heap_size)
         } else {
             index.writer(config.indexer_heap_size)
         }?;
-        Ok(LocalIndex {
+        let merge_policy = merge_policy_storage.load()?;
+        writer.set_merge_policy(merge_policy.merge_policy());
+        let commit_policy = options.commit.or(&config.commit);
+        let index = Arc::new(LocalIndex {
             schema,
+            primary_key,
+            default_search_fields: options.default_search_fields.clone(),
+            default_operator: options.default_operator,
             index,
             reader,
             writer: RwLock::new(writer),
-        })
+            data_dir: config.data_dir.clone(),
+            analyzers,
+            search_tokenizers,
+            merge_policy_storage,
+            merge_policy: RwLock::new(merge_policy),
+            expunging_deletes: Arc::new(AtomicBool::new(false)),
+            commit_scheduler: Arc::new(CommitScheduler::new(&commit_policy)),
+            point_in_times: Arc::new(PointInTimes::default()),
+            max_buckets: config.max_buckets,
+            exports: Arc::new(AtomicUsize::new(0)),
+        });
+        index.commit_scheduler.spawn(Arc::downgrade(&index))?;
+        index.point_in_times.spawn_sweeper()?;
+        Ok(index)
     }
 
     pub async fn add_document(self: &Arc<Self>, req: AddDocReq) -> crate::Result<()> {
         let doc = self.schema.parse_document(&req.doc)?;
         // TODO: если очередь заполнена, то вызов add_document может быть блокирующим
-        self.writer
+        let opstamp = self.writer
             .read()
             .map_err(crate::error::lock_poisoned)?
             .add_document(doc);
-        if req.commit {
-            let this = self.clone();
-            block(move || -> crate::Result<()> {
-                log::debug!("Committing add");
-                this.writer
-                    .write()
-                    .map_err(crate::error::lock_poisoned)?
-                    .commit()?;
-                Ok(())
-            })
-            .await?;
-        }
-        Ok(())
+        self.after_write(opstamp, 1, req.commit, req.refresh).await
     }
 
     pub async fn delete_by_term(self: &Arc<Self>, req: DeleteByTermReq) -> crate::Result<()> {
@@ -101,68 +314,817 @@ impl LocalIndex {
             field: field_name,
             term,
             commit,
+            refresh,
+        } = req;
+
+        let term = self.make_term(field_name, &term)?;
+
+        let opstamp = self.writer
+            .read()
+            .map_err(crate::error::lock_poisoned)?
+            .delete_term(term);
+
+        self.after_write(opstamp, 1, commit, refresh).await
+    }
+
+    /// Deletes the documents matching the query. The index writer only deletes by term,
+    /// so a query of terms is deleted by its terms and other queries by the primary keys
+    /// of the matching documents, in batches: when a batch fails the previous ones stay
+    /// deleted.
+    pub async fn delete_by_query(
+        self: &Arc<Self>,
+        req: DeleteByQueryReq,
+    ) -> crate::Result<DeleteByQueryResp> {
+        let DeleteByQueryReq {
+            query,
+            default_fields,
+            default_operator,
+            commit,
+            refresh,
         } = req;
 
+        let this = self.clone();
+        let (opstamp, deleted) = block(move || -> crate::Result<_> {
+            let searcher = this.reader.searcher();
+            let query = this.make_query(&query, default_fields.as_deref(), default_operator)?;
+            if let Some(terms) = Self::matching_terms(query.as_ref()) {
+                let deleted = searcher.search(query.as_ref(), &Count)? as u64;
+                let opstamp = this.delete_terms(terms, deleted)?;
+                return Ok((opstamp, deleted));
+            }
+
+            let primary_key = this.primary_key.ok_or_else(crate::error::primary_key_not_defined)?;
+            Self::primary_key_stored(&this.schema, primary_key)?;
+            let weight = query.weight(&searcher, false)?;
+            // keys of the matching documents not deleted yet
+            let mut batch = HashMap::<Term, String>::new();
+            let mut batch_docs = 0;
+            let mut opstamp = None;
+            let mut deleted = 0;
+            for segment_reader in searcher.segment_readers() {
+                let store_reader = segment_reader.get_store_reader()?;
+                let mut scorer = weight.scorer(segment_reader, 1.0)?;
+                let mut doc = scorer.doc();
+                while doc != TERMINATED {
+                    if !segment_reader.is_deleted(doc) {
+                        let doc = store_reader.get(doc)?;
+                        batch.entry(this.primary_key_term(&doc)?).or_insert_with(|| {
+                            let key = doc.get_first(primary_key).and_then(|key| serde_json::to_string(key).ok());
+                            key.unwrap_or_default()
+                        });
+                        batch_docs += 1;
+                        if batch.len() >= DELETE_BY_QUERY_BATCH_SIZE {
+                            opstamp = this.delete_keys(&searcher, query.as_ref(), &mut batch, batch_docs)?;
+                            deleted += batch_docs;
+                            batch_docs = 0;
+                        }
+                    }
+                    doc = scorer.advance();
+                }
+            }
+            if !batch.is_empty() {
+                opstamp = this.delete_keys(&searcher, query.as_ref(), &mut batch, batch_docs)?;
+                deleted += batch_docs;
+            }
+            Ok((opstamp, deleted))
+        })
+        .await
+        .map_err(crate::error::blocking)?;
+
+        if let Some(opstamp) = opstamp {
+            // the operations were recorded with their batch
+            self.after_write(opstamp, 0, commit, refresh).await?;
+        }
+        Ok(DeleteByQueryResp { deleted })
+    }
+
+    /// Terms whose documents are exactly the matches of a query of terms, which are
+    /// deleted without reading the documents
+    fn matching_terms(query: &dyn Query) -> Option<Vec<Term>> {
+        if let Some(query) = query.downcast_ref::<TermQuery>() {
+            return Some(vec![query.term().clone()]);
+        }
+        let mut terms = Vec::new();
+        for (occur, query) in query.downcast_ref::<BooleanQuery>()?.clauses() {
+            if *occur != Occur::Should {
+                return None;
+            }
+            terms.extend(Self::matching_terms(query.as_ref())?);
+        }
+        Some(terms)
+    }
+
+    /// Deletes the keys of a batch of matching documents, unless documents not matching
+    /// `query` have one of them: deleting a key deletes every document having it
+    fn delete_keys(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        batch: &mut HashMap<Term, String>,
+        docs: u64,
+    ) -> crate::Result<Option<Opstamp>> {
+        for (term, key) in batch.iter() {
+            let key_query = TermQuery::new(term.clone(), IndexRecordOption::Basic);
+            let with_key = searcher.search(&key_query, &Count)?;
+            let matching = BooleanQuery::intersection(vec![query.box_clone(), Box::new(key_query)]);
+            if searcher.search(&matching, &Count)? != with_key {
+                return Err(crate::error::primary_key_shared(key.clone()));
+            }
+        }
+        let terms = batch.drain().map(|(term, _)| term).collect();
+        self.delete_terms(terms, docs)
+    }
+
+    /// A single operation so that either every term is deleted or none
+    fn delete_terms(&self, terms: Vec<Term>, docs: u64) -> crate::Result<Option<Opstamp>> {
+        if docs == 0 {
+            return Ok(None);
+        }
+        let opstamp = self.writer
+            .read()
+            .map_err(crate::error::lock_poisoned)?
+            .run(terms.into_iter().map(UserOperation::Delete).collect());
+        self.commit_scheduler.record_ops(docs)?;
+        Ok(Some(opstamp))
+    }
+
+    pub async fn update_document(self: &Arc<Self>, req: UpdateDocReq) -> crate::Result<()> {
+        let UpdateDocReq { id, doc, commit, refresh } = req;
+
+        let doc = self.schema.parse_document(&doc)?;
+        let term = self.primary_key_term(&doc)?;
+        let field_entry = self.schema.get_field_entry(term.field());
+        if crate::query::make_term(term.field(), field_entry.field_type(), &id)? != term {
+            return Err(crate::error::primary_key_mismatch(id));
+        }
+
+        // delete and add in a single operation so that readers never see
+        // the document missing or duplicated
+        let opstamp = self.writer
+            .read()
+            .map_err(crate::error::lock_poisoned)?
+            .run(vec![UserOperation::Delete(term), UserOperation::Add(doc)]);
+
+        self.after_write(opstamp, 1, commit, refresh).await
+    }
+
+    /// Applies the newline separated actions of a bulk request, `first_line` being
+    /// the number of the first one. Failed actions are reported per line.
+    pub async fn bulk(self: &Arc<Self>, lines: Vec<u8>, first_line: usize) -> crate::Result<Vec<BulkItemResult>> {
+        let this = self.clone();
+        block(move || -> crate::Result<_> {
+            let items = lines
+                .split(|b| *b == b'\n')
+                .enumerate()
+                .filter(|(_, line)| !line.iter().all(u8::is_ascii_whitespace))
+                .map(|(i, line)| {
+                    let result = serde_json::from_slice::<BulkAction>(line)
+                        .map_err(crate::error::value_parsing_err)
+                        .and_then(|action| this.bulk_action(action));
+                    BulkItemResult {
+                        line: first_line + i,
+                        error: result.err().map(|err| err.to_string()),
+                    }
+                })
+                .collect();
+            Ok(items)
+        })
+        .await
+        .map_err(crate::error::blocking)
+    }
+
+    fn bulk_action(&self, action: BulkAction) -> crate::Result<()> {
+        let writer = self.writer
+            .read()
+            .map_err(crate::error::lock_poisoned)?;
+        match action {
+            BulkAction::Index(doc) => {
+                let doc = self.schema.parse_document(&doc.to_string())?;
+                writer.add_document(doc);
+            }
+            BulkAction::Update(doc) => {
+                let doc = self.schema.parse_document(&doc.to_string())?;
+                let term = self.primary_key_term(&doc)?;
+                writer.run(vec![UserOperation::Delete(term), UserOperation::Add(doc)]);
+            }
+            BulkAction::Delete(DeleteTerm { field, term }) => {
+                let term = self.make_term(field, &term)?;
+                writer.delete_term(term);
+            }
+        }
+        self.commit_scheduler.record_ops(1)
+    }
+
+    fn make_query(
+        &self,
+        query: &SearchQuery,
+        default_fields: Option<&[SearchField]>,
+        default_operator: Option<Operator>,
+    ) -> crate::Result<Box<dyn Query>> {
+        match query {
+            SearchQuery::Parser(query) => {
+                let default_fields = default_fields.unwrap_or(&self.default_search_fields);
+                let default_fields = Self::search_fields(&self.schema, default_fields)?;
+
+                let conjunction = default_operator.unwrap_or(self.default_operator) == Operator::And;
+                QueryStringParser::new(&self.schema, &self.search_tokenizers, default_fields, conjunction)
+                    .parse(query)
+            }
+            SearchQuery::Dsl(query) => query.compile(&self.schema, &self.search_tokenizers),
+        }
+    }
+
+    fn make_term(&self, field_name: String, value: &str) -> crate::Result<Term> {
         let field = self
             .schema
             .get_field(&field_name)
             .ok_or(crate::error::field_not_exist(field_name))?;
         let field_entry = self.schema.get_field_entry(field);
         let field_type = field_entry.field_type();
-        let term = crate::query::make_term(field, field_type, &term)?;
+        crate::query::make_term(field, field_type, value)
+    }
 
-        self.writer
-            .read()
-            .map_err(crate::error::lock_poisoned)?
-            .delete_term(term);
+    fn primary_key_term(&self, doc: &Document) -> crate::Result<Term> {
+        let field = self.primary_key.ok_or_else(crate::error::primary_key_not_defined)?;
+        doc.get_first(field)
+            .and_then(|value| crate::query::value_to_term(field, value))
+            .ok_or_else(|| {
+                crate::error::primary_key_missing(self.schema.get_field_name(field).to_string())
+            })
+    }
 
-        if commit {
+    async fn after_write(
+        self: &Arc<Self>,
+        opstamp: Opstamp,
+        ops: u64,
+        commit: bool,
+        refresh: Refresh,
+    ) -> crate::Result<()> {
+        self.commit_scheduler.record_ops(ops)?;
+        if commit || (refresh == Refresh::WaitFor && !self.commit_scheduler.has_interval()) {
+            log::debug!("Committing write");
+            self.commit().await
+        } else if refresh == Refresh::WaitFor {
             let this = self.clone();
-            block(move || -> crate::Result<_> {
-                log::debug!("Committing delete");
-                this.writer
-                    .write()
-                    .map_err(crate::error::lock_poisoned)?
-                    .commit()?;
-                Ok(())
-            })
-            .await?;
+            block(move || this.commit_scheduler.wait_searchable(opstamp))
+                .await
+                .map_err(crate::error::blocking)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub async fn commit(self: &Arc<Self>) -> crate::Result<()> {
+        let this = self.clone();
+        block(move || this.commit_sync())
+            .await
+            .map_err(crate::error::blocking)
+    }
+
+    /// Commits pending operations and makes them visible to searchers
+    pub fn commit_sync(self: &Arc<Self>) -> crate::Result<()> {
+        let pending = self.commit_scheduler.pending()?;
+        let committed = self.writer
+            .write()
+            .map_err(crate::error::lock_poisoned)?
+            .commit()
+            .and_then(|opstamp| self.reader.reload().map(|_| opstamp));
+        let opstamp = match committed {
+            Ok(opstamp) => opstamp,
+            Err(err) => {
+                let err = crate::Error::from(err);
+                self.commit_scheduler.commit_failed(&err)?;
+                return Err(err);
+            }
+        };
+        self.commit_scheduler.committed(opstamp, pending)?;
+        self.expunge_deletes()
+    }
+
+    /// Starts merging segments without waiting for the merge, which runs in the
+    /// writer's merge threads. The future must be awaited for the merge to start.
+    fn merge(&self, segment_ids: &[SegmentId]) -> crate::Result<impl std::future::Future<Output = tantivy::Result<SegmentMeta>>> {
+        Ok(self.writer
+            .write()
+            .map_err(crate::error::lock_poisoned)?
+            .merge(segment_ids))
+    }
+
+    /// Rewrites alone, in the background, the segments having a higher ratio of
+    /// deleted docs than the merge policy allows
+    fn expunge_deletes(self: &Arc<Self>) -> crate::Result<()> {
+        let segment_ids = self.merge_policy
+            .read()
+            .map_err(crate::error::lock_poisoned)?
+            .segments_to_expunge(&self.index.searchable_segment_metas()?);
+        if segment_ids.is_empty() || self.expunging_deletes.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let this = self.clone();
+        let spawned = std::thread::Builder::new()
+            .name("expunge-deletes".to_string())
+            .spawn(move || {
+                for segment_id in segment_ids {
+                    log::debug!("Expunge deletes of segment {}", segment_id.short_uuid_string());
+                    // the segment may be part of a merge started by the merge policy meanwhile
+                    if let Err(err) = this.merge(&[segment_id]).and_then(|merge| Ok(block_on(merge)?)) {
+                        log::warn!("Deletes of segment {} not expunged: {}", segment_id.short_uuid_string(), err);
+                    }
+                }
+                this.expunging_deletes.store(false, Ordering::SeqCst);
+            });
+        if let Err(err) = spawned {
+            self.expunging_deletes.store(false, Ordering::SeqCst);
+            return Err(err.into());
         }
+        Ok(())
+    }
 
+    /// Merges the searchable segments until there are at most `max_segments`,
+    /// and rewrites the segments having deleted docs
+    pub async fn force_merge(self: &Arc<Self>, max_segments: usize) -> crate::Result<()> {
+        let max_segments = max_segments.max(1);
+        let mut metas = self.index.searchable_segment_metas()?;
+        metas.sort_by_key(SegmentMeta::num_docs);
+        let mut groups = Vec::new();
+        if metas.len() > max_segments {
+            // the smallest segments are merged together, the result counts as one segment
+            let merged = metas.drain(..metas.len() - max_segments + 1);
+            groups.push(merged.map(|meta| meta.id()).collect::<Vec<_>>());
+        }
+        groups.extend(
+            metas
+                .iter()
+                .filter(|meta| meta.has_deletes())
+                .map(|meta| vec![meta.id()]),
+        );
+        for segment_ids in groups {
+            let merge = self.merge(&segment_ids)?;
+            merge.await?;
+        }
         Ok(())
     }
 
-    pub async fn update_document(&self) -> crate::Result<()> {
-        todo!()
+    pub fn merge_policy(&self) -> crate::Result<MergePolicyConfig> {
+        Ok(self.merge_policy.read().map_err(crate::error::lock_poisoned)?.clone())
+    }
+
+    pub fn set_merge_policy(&self, merge_policy: MergePolicyConfig) -> crate::Result<()> {
+        merge_policy.validate()?;
+        let mut current = self.merge_policy.write().map_err(crate::error::lock_poisoned)?;
+        self.merge_policy_storage.store(&merge_policy)?;
+        self.writer
+            .read()
+            .map_err(crate::error::lock_poisoned)?
+            .set_merge_policy(merge_policy.merge_policy());
+        *current = merge_policy;
+        Ok(())
     }
 
     pub async fn search(
         self: &Arc<Self>,
         req: SearchReq,
     ) -> crate::Result<SearchResp> {
+        let (resp, _) = self.search_with_positions(req).await?;
+        Ok(resp)
+    }
+
+    /// Search also returning the sort positions of the documents, none when sorting by relevance
+    pub async fn search_with_positions(
+        self: &Arc<Self>,
+        req: SearchReq,
+    ) -> crate::Result<(SearchResp, Vec<SortPosition>)> {
         let this = self.clone();
         block(move || -> crate::Result<_> {
-            let searcher = this.reader.searcher();
-            let query_parser = tantivy::query::QueryParser::for_index(&this.index, vec![]);
-            let query = query_parser.parse_query(&req.query)?;
-            let collector =
-                tantivy::collector::TopDocs::with_limit(req.limit).and_offset(req.offset);
-            let docs = searcher.search(&query, &collector)?;
-
-            let docs = docs.iter()
-                .map(|(score, doc_address)| -> tantivy::Result<_> {
-                    let named_doc = searcher
-                        .doc(*doc_address)
-                        .map(|doc| this.schema.to_named_doc(&doc))?;
+            let searcher = this.searcher(req.pit.as_deref())?;
+            let query = this.make_query(
+                &req.query,
+                req.default_fields.as_deref(),
+                req.default_operator,
+            )?;
+            let sort = match req.sort.as_deref() {
+                Some(sort) if !sort.is_empty() => Some(Sort::new(&this.schema, sort)?),
+                // cursors are sort positions, relevance gives them one
+                _ if req.pit.is_some() || req.search_after.is_some() => Some(Sort::relevance()),
+                _ => None,
+            }
+            .map(Arc::new);
+            let after = req.search_after
+                .as_deref()
+                .zip(sort.as_deref())
+                .map(|(cursor, sort)| SortPosition::from_cursor(cursor, sort))
+                .transpose()?;
+            let source = req.source
+                .as_ref()
+                .map(|source| SourceFilter::new(&this.schema, source))
+                .transpose()?;
+
+            let mut collector = MultiCollector::new();
+            // TopDocs panics on a zero limit, which is fine when only aggregations are needed
+            let top_docs_handle = (req.limit > 0 && sort.is_none()).then(|| {
+                collector.add_collector(TopDocs::with_limit(req.limit).and_offset(req.offset))
+            });
+            let sorted_handle = sort.as_ref().filter(|_| req.limit > 0).map(|sort| {
+                collector.add_collector(SortCollector::new(sort.clone(), req.limit, req.offset, after))
+            });
+            let count_handle = req.total_hits.then(|| collector.add_collector(Count));
+            let aggs_handles = req.aggs
+                .as_ref()
+                .map(|aggs| AggsHandles::new(&this.schema, aggs, this.max_buckets, &mut collector))
+                .transpose()?;
+
+            let mut fruits = searcher.search(&query, &collector)?;
+            let docs = match (top_docs_handle, sorted_handle) {
+                (Some(handle), _) => handle
+                    .extract(&mut fruits)
+                    .into_iter()
+                    .map(|(score, doc_address)| (score, doc_address, None))
+                    .collect(),
+                (_, Some(handle)) => handle
+                    .extract(&mut fruits)
+                    .into_iter()
+                    .map(|doc| (doc.score, doc.position.address, Some(doc.position)))
+                    .collect::<Vec<_>>(),
+                _ => Vec::new(),
+            };
+            let positions = docs
+                .iter()
+                .filter_map(|(_, _, position)| position.clone())
+                .collect();
+            let total_hits = count_handle.map(|handle| handle.extract(&mut fruits));
+            let aggs = aggs_handles.map(|handles| handles.extract(&mut fruits)).transpose()?;
+
+            let highlighter = req.highlight
+                .map(|highlight| Highlighter::new(&searcher, &this.schema, query.as_ref(), highlight))
+                .transpose()?;
+
+            let explain = req.explain;
+            let docs = docs.into_iter()
+                .map(|(score, doc_address, position)| -> tantivy::Result<_> {
+                    let doc = searcher.doc(doc_address)?;
+                    let sort_values = position
+                        .as_ref()
+                        .zip(sort.as_deref())
+                        .map(|(position, sort)| sort.values(&position.keys));
+                    let explanation = explain
+                        .then(|| query.explain(&searcher, doc_address))
+                        .transpose()?;
                     Ok(ScoredDocument {
-                        score: *score,
-                        doc: named_doc,
+                        score,
+                        highlights: highlighter.as_ref().map(|h| h.highlight(&doc)),
+                        doc: match &source {
+                            Some(source) => source.to_named_doc(&this.schema, &doc),
+                            None => this.schema.to_named_doc(&doc),
+                        },
+                        sort: sort_values,
+                        cursor: position.as_ref().map(SortPosition::cursor),
+                        explanation,
+                        index: None,
                     })
                 })
                 .collect::<Result<Vec<_>, _>>()?;
-            Ok(SearchResp { docs })
+            let resp = SearchResp {
+                docs,
+                total_hits,
+                aggs,
+                skipped_indices: Vec::new(),
+            };
+            Ok((resp, positions))
         })
         .await
-        .map_err(From::from)
+        .map_err(crate::error::blocking)
+    }
+
+    /// Types of the sort fields, which must be the same to merge the hits of several indices
+    pub fn sort_types(&self, fields: &[SortField]) -> crate::Result<Vec<Option<FastType>>> {
+        Ok(Sort::new(&self.schema, fields)?.fast_types())
+    }
+
+    /// Returns the first document having `value` in `field`
+    pub async fn get_document(
+        self: &Arc<Self>,
+        field: String,
+        value: String,
+    ) -> crate::Result<NamedFieldDocument> {
+        let this = self.clone();
+        block(move || -> crate::Result<_> {
+            let searcher = this.reader.searcher();
+            let query = this.key_query(field, &value)?;
+            this.first_document(&searcher, &query)?
+                .ok_or_else(crate::error::document_not_found)
+        })
+        .await
+        .map_err(crate::error::blocking)
+    }
+
+    pub async fn document_exists(
+        self: &Arc<Self>,
+        field: String,
+        value: String,
+    ) -> crate::Result<bool> {
+        let this = self.clone();
+        block(move || -> crate::Result<_> {
+            let searcher = this.reader.searcher();
+            let query = this.key_query(field, &value)?;
+            Ok(searcher.search(&query, &Count)? > 0)
+        })
+        .await
+        .map_err(crate::error::blocking)
+    }
+
+    pub async fn get_documents(self: &Arc<Self>, req: MultiGetReq) -> crate::Result<MultiGetResp> {
+        let this = self.clone();
+        block(move || -> crate::Result<_> {
+            let searcher = this.reader.searcher();
+            let docs = req.docs
+                .into_iter()
+                .map(|key| -> crate::Result<_> {
+                    let query = this.key_query(key.field, &key.value.as_str())?;
+                    let doc = this.first_document(&searcher, &query)?;
+                    Ok(MultiGetItem {
+                        found: doc.is_some(),
+                        doc,
+                    })
+                })
+                .collect::<crate::Result<_>>()?;
+            Ok(MultiGetResp { docs })
+        })
+        .await
+        .map_err(crate::error::blocking)
+    }
+
+    /// Explains the score of the document with the primary key `id` for the query
+    pub async fn explain(
+        self: &Arc<Self>,
+        id: String,
+        req: ExplainReq,
+    ) -> crate::Result<ScoredDocument> {
+        let this = self.clone();
+        block(move || -> crate::Result<_> {
+            let primary_key = this.primary_key.ok_or_else(crate::error::primary_key_not_defined)?;
+            let searcher = this.reader.searcher();
+            let key_query = this.key_query(this.schema.get_field_name(primary_key).to_string(), &id)?;
+            let doc_address = searcher
+                .search(&key_query, &TopDocs::with_limit(1))?
+                .first()
+                .map(|(_, doc_address)| *doc_address)
+                .ok_or_else(crate::error::document_not_found)?;
+            let query = this.make_query(
+                &req.query,
+                req.default_fields.as_deref(),
+                req.default_operator,
+            )?;
+            let explanation = query
+                .explain(&searcher, doc_address)
+                .map_err(|err| match err {
+                    TantivyError::InvalidArgument(_) => crate::error::document_not_matching(),
+                    err => err.into(),
+                })?;
+            let doc = searcher.doc(doc_address)?;
+            Ok(ScoredDocument {
+                score: explanation.value(),
+                doc: this.schema.to_named_doc(&doc),
+                highlights: None,
+                sort: None,
+                cursor: None,
+                explanation: Some(explanation),
+                index: None,
+            })
+        })
+        .await
+        .map_err(crate::error::blocking)
+    }
+
+    fn key_query(&self, field_name: String, value: &str) -> crate::Result<TermQuery> {
+        let field = self
+            .schema
+            .get_field(&field_name)
+            .ok_or_else(|| crate::error::field_not_exist(field_name.clone()))?;
+        if !self.schema.get_field_entry(field).is_indexed() {
+            return Err(crate::error::field_not_indexed(field_name));
+        }
+        let term = self.make_term(field_name, value)?;
+        Ok(TermQuery::new(term, IndexRecordOption::Basic))
+    }
+
+    fn first_document(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+    ) -> crate::Result<Option<NamedFieldDocument>> {
+        let top_docs = searcher.search(query, &TopDocs::with_limit(1))?;
+        match top_docs.first() {
+            Some((_, doc_address)) => {
+                let doc = searcher.doc(*doc_address)?;
+                Ok(Some(self.schema.to_named_doc(&doc)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub async fn count(self: &Arc<Self>, req: CountReq) -> crate::Result<CountResp> {
+        let this = self.clone();
+        block(move || -> crate::Result<_> {
+            let searcher = this.searcher(req.pit.as_deref())?;
+            let query = match &req.query {
+                Some(query) => this.make_query(
+                    query,
+                    req.default_fields.as_deref(),
+                    req.default_operator,
+                )?,
+                None => Box::new(AllQuery),
+            };
+            let count = searcher.search(&query, &Count)?;
+            Ok(CountResp {
+                count,
+                skipped_indices: Vec::new(),
+            })
+        })
+        .await
+        .map_err(crate::error::blocking)
+    }
+
+    /// Searcher of the point in time or of the latest committed generation
+    fn searcher(&self, pit: Option<&str>) -> crate::Result<LeasedItem<Searcher>> {
+        match pit {
+            Some(id) => self.point_in_times.searcher(id),
+            None => Ok(self.reader.searcher()),
+        }
+    }
+
+    /// Streams every document matching the query as NDJSON chunks.
+    /// The documents are read in a dedicated thread which waits while the client is slower.
+    /// The thread keeps the segments of the searcher, not the searcher, which goes back to
+    /// the pool of the index or of the point in time for the searches.
+    pub async fn export(
+        self: &Arc<Self>,
+        req: ExportReq,
+    ) -> crate::Result<mpsc::Receiver<crate::Result<Bytes>>> {
+        let permit = ExportPermit::acquire(&self.exports)?;
+        let this = self.clone();
+        let (segment_readers, weight) = block(move || -> crate::Result<_> {
+            let searcher = this.searcher(req.pit.as_deref())?;
+            let query = match &req.query {
+                Some(query) => this.make_query(query, None, None)?,
+                None => Box::new(AllQuery),
+            };
+            let weight = query.weight(&searcher, false)?;
+            Ok((searcher.segment_readers().to_vec(), weight))
+        })
+        .await
+        .map_err(crate::error::blocking)?;
+
+        let schema = self.schema.clone();
+        let (mut sender, receiver) = mpsc::channel(EXPORT_BUFFERED_CHUNKS);
+        std::thread::Builder::new()
+            .name("export".to_string())
+            .spawn(move || {
+                let _permit = permit;
+                if let Err(err) = Self::export_docs(&schema, &segment_readers, weight.as_ref(), &mut sender) {
+                    log::error!("Export failed: {}", err);
+                    // the client sees a truncated response
+                    let _ = sender.try_send(Err(err));
+                }
+            })?;
+        Ok(receiver)
+    }
+
+    /// Returns once every document is sent or the receiver is dropped
+    fn export_docs(
+        schema: &Schema,
+        segment_readers: &[SegmentReader],
+        weight: &dyn Weight,
+        sender: &mut mpsc::Sender<crate::Result<Bytes>>,
+    ) -> crate::Result<()> {
+        let mut chunk = Vec::with_capacity(EXPORT_CHUNK_SIZE);
+        for segment_reader in segment_readers {
+            let store_reader = segment_reader.get_store_reader()?;
+            let mut scorer = weight.scorer(segment_reader, 1.0)?;
+            let mut doc = scorer.doc();
+            while doc != TERMINATED {
+                if !segment_reader.is_deleted(doc) {
+                    let document = store_reader.get(doc)?;
+                    serde_json::to_writer(&mut chunk, &schema.to_named_doc(&document))?;
+                    chunk.push(b'\n');
+                    if chunk.len() >= EXPORT_CHUNK_SIZE {
+                        let full = std::mem::replace(
+                            &mut chunk,
+                            Vec::with_capacity(EXPORT_CHUNK_SIZE),
+                        );
+                        if !Self::send_chunk(sender, full)? {
+                            return Ok(());
+                        }
+                    }
+                }
+                doc = scorer.advance();
+            }
+        }
+        if !chunk.is_empty() {
+            Self::send_chunk(sender, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the client to make room for the chunk, false if it went away
+    fn send_chunk(sender: &mut mpsc::Sender<crate::Result<Bytes>>, chunk: Vec<u8>) -> crate::Result<bool> {
+        let timeout = Instant::now() + EXPORT_SEND_TIMEOUT;
+        let mut chunk = Ok(chunk.into());
+        loop {
+            match sender.try_send(chunk) {
+                Ok(()) => return Ok(true),
+                Err(err) if err.is_disconnected() => return Ok(false),
+                Err(err) => chunk = err.into_inner(),
+            }
+            if Instant::now() >= timeout {
+                return Err(crate::error::export_timed_out());
+            }
+            std::thread::sleep(EXPORT_SEND_POLL_INTERVAL);
+        }
+    }
+
+    /// Document counts and on disk sizes of the searchable segments
+    pub async fn stats(self: &Arc<Self>) -> crate::Result<IndexStats> {
+        let this = self.clone();
+        block(move || -> crate::Result<_> {
+            let directory = this.index.directory();
+            let mut totals = StatsTotals::default();
+            let mut segments = Vec::new();
+            for meta in this.index.searchable_segment_metas()? {
+                let mut size_in_bytes = 0;
+                for path in meta.list_files() {
+                    // the list has a delete file even when the segment has no deletes
+                    let size = match directory.open_read(&path) {
+                        Ok(file) => file.len() as u64,
+                        Err(OpenReadError::FileDoesNotExist(_)) => continue,
+                        Err(err) => return Err(err.into()),
+                    };
+                    let extension = path.extension().and_then(OsStr::to_str).unwrap_or_default();
+                    *totals.files.entry(extension.to_string()).or_default() += size;
+                    size_in_bytes += size;
+                }
+                totals.num_docs += meta.num_docs() as u64;
+                totals.num_deleted_docs += meta.num_deleted_docs() as u64;
+                totals.size_in_bytes += size_in_bytes;
+                segments.push(SegmentStats {
+                    id: meta.id().uuid_string(),
+                    max_doc: meta.max_doc(),
+                    num_docs: meta.num_docs(),
+                    num_deleted_docs: meta.num_deleted_docs(),
+                    delete_opstamp: meta.delete_opstamp(),
+                    size_in_bytes,
+                });
+            }
+            totals.num_segments = segments.len();
+            Ok(IndexStats {
+                totals,
+                opstamp: this.index.load_metas()?.opstamp,
+                segments,
+            })
+        })
+        .await
+        .map_err(crate::error::blocking)
+    }
+
+    /// Tokens produced by an analyzer of the index or by an inline analyzer,
+    /// which can't read files since reading the index is enough to analyze
+    pub fn analyze(&self, req: AnalyzeReq) -> crate::Result<AnalyzeResp> {
+        let analyzer = match (req.analyzer, req.search) {
+            (AnalyzerReq::Config(config), _) if config.reads_files() => {
+                return Err(crate::error::invalid_query(
+                    "Inline analyzers can't read synonyms files".to_string(),
+                ))
+            }
+            (AnalyzerReq::Name(name), false) => self.index
+                .tokenizers()
+                .get(&name)
+                .ok_or_else(|| crate::error::analyzer_not_exist(name))?,
+            (AnalyzerReq::Name(name), true) => self.search_tokenizers
+                .get(&name)
+                .ok_or_else(|| crate::error::analyzer_not_exist(name))?,
+            (AnalyzerReq::Config(config), false) => config.make_analyzer(&self.data_dir)?,
+            (AnalyzerReq::Config(config), true) => config.make_search_analyzer(&self.data_dir)?,
+        };
+        let mut tokens = Vec::new();
+        analyzer
+            .token_stream(&req.text)
+            .process(&mut |token| tokens.push(token.clone()));
+        Ok(AnalyzeResp { tokens })
+    }
+
+    pub async fn open_point_in_time(self: &Arc<Self>, keep_alive: Duration) -> crate::Result<String> {
+        let this = self.clone();
+        block(move || this.point_in_times.open(&this.index, keep_alive))
+            .await
+            .map_err(crate::error::blocking)
+    }
+
+    pub fn close_point_in_time(&self, id: &str) -> crate::Result<bool> {
+        self.point_in_times.close(id)
+    }
+}
+
+impl Drop for LocalIndex {
+    fn drop(&mut self) {
+        self.commit_scheduler.stop();
     }
 }
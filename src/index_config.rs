@@ -1,12 +1,22 @@
+use std::path::{Path, PathBuf};
+
 use tantivy::schema::{Schema as TantivySchema};
+use tantivy::store::Compressor;
 use tantivy::tokenizer::{
     TextAnalyzer, FacetTokenizer, NgramTokenizer, RawTokenizer, SimpleTokenizer,
     BoxTokenFilter, AlphaNumOnlyFilter, AsciiFoldingFilter, RemoveLongFilter, Stemmer, LowerCaser,
+    StopWordFilter,
 };
 use serde::{Serialize, Deserialize};
 
+use crate::config::CommitPolicy;
+use crate::merge_policy::MergePolicyConfig;
+use crate::stop_words::StopWordsLanguage;
+use crate::synonyms::{SynonymFilter, Synonyms};
+use crate::tokenizers::{RegexTokenizer, UnicodeWordsTokenizer, WhitespaceTokenizer};
+
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NgramTokenizerConfig {
     /// min size of the n-gram
     min_gram: usize,
@@ -22,7 +32,13 @@ impl From<&NgramTokenizerConfig> for NgramTokenizer {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegexTokenizerConfig {
+    /// regex matching the tokens, e.g. `[\w.]+` for dotted identifiers
+    pattern: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "lowercase")]
 pub enum TokenizerConfig {
@@ -30,9 +46,14 @@ pub enum TokenizerConfig {
     Simple,
     Ngram(NgramTokenizerConfig),
     Facet,
+    Whitespace,
+    Regex(RegexTokenizerConfig),
+    /// UAX#29 word segmentation
+    #[serde(rename = "unicode_words")]
+    UnicodeWords,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RemoveLongFilterConfig {
     limit: usize,
 }
@@ -43,7 +64,7 @@ impl From<&RemoveLongFilterConfig> for RemoveLongFilter {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StemmerConfig {
     lang: tantivy::tokenizer::Language,
 }
@@ -54,12 +75,59 @@ impl From<&StemmerConfig> for Stemmer {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Words are compared to the tokens as they are, so the filter usually follows `lowercase`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StopFilterConfig {
+    Language { lang: StopWordsLanguage },
+    Words { words: Vec<String> },
+}
+
+impl From<&StopFilterConfig> for StopWordFilter {
+    fn from(conf: &StopFilterConfig) -> Self {
+        match conf {
+            StopFilterConfig::Language { lang } => lang.filter(),
+            StopFilterConfig::Words { words } => Self::remove(words.clone()),
+        }
+    }
+}
+
+/// Rules given inline and in a file are merged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SynonymFilterConfig {
+    /// rules like `laptop, notebook` or `tv => television`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    synonyms: Vec<String>,
+    /// file of rules, one per line, relative to the synonyms dir of the data dir.
+    /// Search filters read it again on reload, index filters copy its rules at creation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    synonyms_path: Option<PathBuf>,
+}
+
+impl SynonymFilterConfig {
+    fn make_filter(&self, data_dir: &Path) -> crate::Result<SynonymFilter> {
+        let mut synonyms = Synonyms::parse(self.synonyms.iter().map(String::as_str))?;
+        if let Some(path) = &self.synonyms_path {
+            synonyms.extend(Synonyms::load(data_dir, path)?);
+        }
+        Ok(SynonymFilter::new(synonyms))
+    }
+
+    fn inline_file(&mut self, data_dir: &Path) -> crate::Result<()> {
+        if let Some(path) = self.synonyms_path.take() {
+            self.synonyms.extend(Synonyms::read_rules(data_dir, &path)?);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 pub enum TokenFilterConfig {
     Lowercase,
-    //Stop,
+    Stop(StopFilterConfig),
+    Synonym(SynonymFilterConfig),
     RemoveLong(RemoveLongFilterConfig),
     AlphaNum,
     AsciiFolding,
@@ -67,50 +135,174 @@ pub enum TokenFilterConfig {
 }
 
 impl TokenFilterConfig {
-    pub fn make_token_filter(&self) -> BoxTokenFilter {
-        match self {
+    /// Files of the filters are read from `data_dir`
+    pub fn make_token_filter(&self, data_dir: &Path) -> crate::Result<BoxTokenFilter> {
+        Ok(match self {
             TokenFilterConfig::Lowercase => LowerCaser.into(),
+            TokenFilterConfig::Stop(conf) => StopWordFilter::from(conf).into(),
+            TokenFilterConfig::Synonym(conf) => conf.make_filter(data_dir)?.into(),
             TokenFilterConfig::RemoveLong(conf) => RemoveLongFilter::from(conf).into(),
             TokenFilterConfig::AlphaNum => AlphaNumOnlyFilter.into(),
             TokenFilterConfig::AsciiFolding => AsciiFoldingFilter.into(),
             TokenFilterConfig::Stemmer(conf) => Stemmer::from(conf).into(),
-        }
+        })
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnalyzerConfig {
     pub name: String,
     pub tokenizer: TokenizerConfig,
     #[serde(default)]
     pub token_filters: Vec<TokenFilterConfig>,
+    /// filters applied after `token_filters` to the queries only, they may change
+    /// without reindexing, e.g. query time synonyms
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub search_token_filters: Vec<TokenFilterConfig>,
 }
 
 impl AnalyzerConfig {
-    pub fn make_analyzer(&self) -> TextAnalyzer {
-        let filters = self.token_filters
+    /// Analyzer of the indexed documents
+    pub fn make_analyzer(&self, data_dir: &Path) -> crate::Result<TextAnalyzer> {
+        self.build(self.token_filters.iter(), data_dir)
+    }
+
+    /// Copies the rules of the synonyms files of `token_filters` in the config, as
+    /// the indexed terms must not change with the files: only search filters read them
+    pub fn inline_synonyms_files(&mut self, data_dir: &Path) -> crate::Result<()> {
+        for filter in &mut self.token_filters {
+            if let TokenFilterConfig::Synonym(conf) = filter {
+                conf.inline_file(data_dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a filter reads a file of the data dir
+    pub fn reads_files(&self) -> bool {
+        self.token_filters
             .iter()
-            .map(TokenFilterConfig::make_token_filter)
-            .collect::<Vec<_>>();
+            .chain(&self.search_token_filters)
+            .any(|filter| matches!(filter, TokenFilterConfig::Synonym(conf) if conf.synonyms_path.is_some()))
+    }
+
+    /// Analyzer of the queries
+    pub fn make_search_analyzer(&self, data_dir: &Path) -> crate::Result<TextAnalyzer> {
+        self.build(self.token_filters.iter().chain(&self.search_token_filters), data_dir)
+    }
+
+    fn build<'a>(
+        &self,
+        filters: impl Iterator<Item = &'a TokenFilterConfig>,
+        data_dir: &Path,
+    ) -> crate::Result<TextAnalyzer> {
+        let filters = filters
+            .map(|filter| filter.make_token_filter(data_dir))
+            .collect::<crate::Result<Vec<_>>>()?;
 
         use TokenizerConfig::*;
-        match &self.tokenizer {
+        Ok(match &self.tokenizer {
             Raw => TextAnalyzer::new(RawTokenizer, filters),
             Simple => TextAnalyzer::new(SimpleTokenizer, filters),
             Ngram(conf) => TextAnalyzer::new(NgramTokenizer::from(conf), filters),
             Facet => TextAnalyzer::new(FacetTokenizer, filters),
-        }
+            Whitespace => TextAnalyzer::new(WhitespaceTokenizer, filters),
+            Regex(conf) => TextAnalyzer::new(RegexTokenizer::new(&conf.pattern)?, filters),
+            UnicodeWords => TextAnalyzer::new(UnicodeWordsTokenizer, filters),
+        })
     }
 }
 
 pub type Analyzers = Vec<AnalyzerConfig>;
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchField {
+    pub field: String,
+    #[serde(default)]
+    pub boost: Option<tantivy::Score>,
+}
+
+/// How the query parser combines terms without an explicit operator
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operator {
+    And,
+    #[default]
+    Or,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct IndexOptions {
+    /// field which uniquely identifies a document, used for upserts
+    #[serde(default)]
+    pub primary_key: Option<String>,
+    /// overrides the commit policy from the search config
+    #[serde(default)]
+    pub commit: CommitPolicy,
+    /// fields searched by the query parser when a query doesn't name one
+    #[serde(default)]
+    pub default_search_fields: Vec<SearchField>,
+    #[serde(default)]
+    pub default_operator: Operator,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(from = "IndexSettingsDef")]
+pub struct IndexSettings {
+    /// tantivy settings, given next to the merge policy
+    #[serde(flatten)]
+    pub tantivy: tantivy::IndexSettings,
+    #[serde(default)]
+    pub merge_policy: MergePolicyConfig,
+}
+
+impl Default for IndexSettings {
+    fn default() -> Self {
+        IndexSettingsDef::default().into()
+    }
+}
+
+impl IndexSettings {
+    pub fn validate(&self) -> crate::Result<()> {
+        // the lz4 compressor of tantivy 0.16 sets the length of its output past
+        // the reserved capacity, indices using it can be read but not created
+        if self.tantivy.docstore_compression == Compressor::Lz4 {
+            return Err(crate::error::invalid_settings(
+                "docstore_compression lz4 isn't supported, use snappy".to_string(),
+            ));
+        }
+        self.merge_policy.validate()
+    }
+}
+
+/// IndexSettings as given by the user, the doc store compression defaults to
+/// snappy rather than tantivy's lz4
+#[derive(Default, Deserialize)]
+struct IndexSettingsDef {
+    #[serde(default)]
+    docstore_compression: Option<Compressor>,
+    #[serde(flatten)]
+    tantivy: tantivy::IndexSettings,
+    #[serde(default)]
+    merge_policy: MergePolicyConfig,
+}
+
+impl From<IndexSettingsDef> for IndexSettings {
+    fn from(def: IndexSettingsDef) -> Self {
+        let mut tantivy = def.tantivy;
+        tantivy.docstore_compression = def.docstore_compression.unwrap_or(Compressor::Snappy);
+        Self { tantivy, merge_policy: def.merge_policy }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct IndexConfig {
     #[serde(default)]
-    pub settings: tantivy::IndexSettings,
+    pub settings: IndexSettings,
     #[serde(default)]
     pub analyzers: Analyzers,
+    #[serde(default)]
+    pub options: IndexOptions,
     pub schema: TantivySchema,
 }
 
@@ -129,9 +321,59 @@ mod test {
         let config: IndexConfig = serde_json::from_str(config).unwrap();
 
         assert_eq!(config.analyzers.len(), 0);
+        assert!(config.options.primary_key.is_none());
         assert_eq!(config.schema.fields().count(), 0);
     }
 
+    #[test]
+    fn test_index_settings_docstore_compression() {
+        let settings: IndexSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(settings.tantivy.docstore_compression, Compressor::Snappy);
+        assert!(settings.validate().is_ok());
+
+        let settings: IndexSettings = serde_json::from_str(r#"{"docstore_compression": "lz4"}"#).unwrap();
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_index_config_with_primary_key_deserialize() {
+        let config = r#"
+{
+    "options": {
+        "primary_key": "id"
+    },
+    "schema": []
+}
+        "#;
+        let config: IndexConfig = serde_json::from_str(config).unwrap();
+
+        assert_eq!(config.options.primary_key.as_deref(), Some("id"));
+        assert_eq!(config.options.default_operator, Operator::Or);
+    }
+
+    #[test]
+    fn test_index_config_with_default_search_fields_deserialize() {
+        let config = r#"
+{
+    "options": {
+        "default_search_fields": [
+            { "field": "title", "boost": 2.0 },
+            { "field": "body" }
+        ],
+        "default_operator": "and"
+    },
+    "schema": []
+}
+        "#;
+        let config: IndexConfig = serde_json::from_str(config).unwrap();
+
+        let fields = &config.options.default_search_fields;
+        assert_eq!(fields[0].field, "title");
+        assert_eq!(fields[0].boost, Some(2.0));
+        assert_eq!(fields[1].boost, None);
+        assert_eq!(config.options.default_operator, Operator::And);
+    }
+
     #[test]
     fn test_index_config_with_custom_analyzer_deserialize() {
         let config = r#"
@@ -166,4 +408,37 @@ mod test {
             _ => panic!("Expected token filter 'stemmer'")
         }
     }
+
+    #[test]
+    fn test_stop_filter_config() {
+        let filters = r#"
+[
+    { "type": "lowercase" },
+    { "type": "stop", "lang": "Russian" },
+    { "type": "stop", "words": ["fox"] }
+]
+        "#;
+        let filters: Vec<TokenFilterConfig> = serde_json::from_str(filters).unwrap();
+        assert!(matches!(
+            filters[1],
+            TokenFilterConfig::Stop(StopFilterConfig::Language { lang: StopWordsLanguage::Russian })
+        ));
+        // stored in analyzers.json
+        let json = serde_json::to_value(&filters[2]).unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "stop", "words": ["fox"] }));
+
+        let analyzer = AnalyzerConfig {
+            name: "stop".to_string(),
+            tokenizer: TokenizerConfig::Simple,
+            token_filters: filters,
+            search_token_filters: Vec::new(),
+        };
+        let mut tokens = Vec::new();
+        analyzer
+            .make_analyzer(Path::new("."))
+            .unwrap()
+            .token_stream("И лиса, и Fox")
+            .process(&mut |token| tokens.push(token.text.clone()));
+        assert_eq!(tokens, vec!["лиса"]);
+    }
 }
\ No newline at end of file
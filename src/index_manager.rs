@@ -3,81 +3,342 @@ use std::fs;
 use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 
+use actix_web::web::block;
+
+use crate::aliases::{AliasActionsReq, Aliases};
 use crate::config;
+use crate::dto::{IndexInfo, IndexStatus};
 use crate::index::LocalIndex;
 use crate::index_config::IndexConfig;
+use crate::index_registry::{IndexEntry, IndexRegistry, IndexState};
+use crate::synonyms::SYNONYMS_DIR;
+use crate::utils::json_file_storage::JsonFileStorage;
+use crate::utils::wildcard::Wildcard;
+
+const ALIASES_FILE: &str = "aliases.json";
+const REGISTRY_FILE: &str = "indices.json";
+
+enum LoadedIndex {
+    Open(Arc<LocalIndex>),
+    /// reason the index could not be opened
+    Failed(String),
+}
 
-// TODO: file with indicies list
+pub type NamedIndex = (String, Arc<LocalIndex>);
+
+/// Index matched by an index expression
+pub struct ResolvedIndex {
+    pub name: String,
+    /// named in the expression, not only matched by an alias or a wildcard
+    pub explicit: bool,
+}
 
 pub struct IndexManager {
     conf: config::Search,
-    indices: RwLock<HashMap<String, Arc<LocalIndex>>>,
+    registry_storage: JsonFileStorage<IndexRegistry>,
+    registry: RwLock<IndexRegistry>,
+    /// every registered index, opened at startup
+    indices: RwLock<HashMap<String, LoadedIndex>>,
+    alias_storage: JsonFileStorage<Aliases>,
+    aliases: RwLock<Aliases>,
 }
 
 impl IndexManager {
     pub fn new(conf: config::Search) -> crate::Result<Self> {
         fs::create_dir_all(&conf.data_dir)?;
+        let registry_path = conf.data_dir.join(REGISTRY_FILE);
+        let registry_storage = JsonFileStorage::new(registry_path.clone());
+        let registry = if registry_path.exists() {
+            registry_storage.load()?
+        } else {
+            let registry = Self::find_indices(&conf)?;
+            registry_storage.store(&registry)?;
+            registry
+        };
+        let indices = registry
+            .iter()
+            .map(|(name, entry)| (name.clone(), Self::load_index(&conf, name, entry)))
+            .collect();
+
+        let alias_storage = JsonFileStorage::new(conf.data_dir.join(ALIASES_FILE));
+        let aliases = alias_storage.load()?;
         Ok(Self {
             conf,
-            indices: RwLock::default(),
+            registry_storage,
+            registry: RwLock::new(registry),
+            indices: RwLock::new(indices),
+            alias_storage,
+            aliases: RwLock::new(aliases),
         })
     }
 
-    pub async fn create_index(&self, name: String, index_conf: &IndexConfig) -> crate::Result<()> {
-        let path = self.index_path(&name)?;
-        fs::create_dir_all(&path)?;
-        let index = LocalIndex::creare_in_dir(&path, index_conf, &self.conf)?;
-        let index = Arc::new(index);
-        self.insert_index(name, index)
+    /// Registers the index directories of a data dir which has no registry yet
+    fn find_indices(conf: &config::Search) -> crate::Result<IndexRegistry> {
+        let mut registry = IndexRegistry::default();
+        for entry in fs::read_dir(&conf.data_dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                if is_valid_name(name) {
+                    log::info!("Register index '{}' found in the data dir", name);
+                    let created = entry.metadata()?.created().ok();
+                    registry.insert(name.to_string(), IndexEntry::found(created));
+                }
+            }
+        }
+        Ok(registry)
     }
 
-    pub async fn delete_index(&self, name: &str) -> crate::Result<()> {
+    fn load_index(conf: &config::Search, name: &str, entry: &IndexEntry) -> LoadedIndex {
+        if entry.state == IndexState::Creating {
+            log::error!("Index '{}' was not completely created", name);
+            return LoadedIndex::Failed("Index creation was not completed".to_string());
+        }
+        match LocalIndex::open_in_dir(&conf.data_dir.join(name), conf) {
+            Ok(index) => {
+                log::info!("Index '{}' opened", name);
+                LoadedIndex::Open(index)
+            }
+            Err(err) => {
+                log::error!("Failed to open index '{}': {}", name, err);
+                LoadedIndex::Failed(err.to_string())
+            }
+        }
+    }
+
+    pub async fn create_index(&self, name: String, index_conf: IndexConfig) -> crate::Result<()> {
+        let path = self.index_path(&name)?;
+        {
+            let mut registry = self.registry.write().map_err(crate::error::lock_poisoned)?;
+            if registry.contains(&name)
+                || self.aliases.read().map_err(crate::error::lock_poisoned)?.contains(&name)
+            {
+                return Err(crate::error::name_already_used(name));
+            }
+            // registered first, so that the name is reserved while the index is created
+            // and an interrupted creation is reported at the next startup
+            registry.insert(name.clone(), IndexEntry::creating(&index_conf)?);
+            self.registry_storage.store(&registry)?;
+        }
+
+        let conf = self.conf.clone();
+        let created = block(move || {
+            let created = fs::create_dir_all(&path)
+                .map_err(From::from)
+                .and_then(|_| LocalIndex::creare_in_dir(&path, &index_conf, &conf));
+            if created.is_err() {
+                let _ = fs::remove_dir_all(&path);
+            }
+            created
+        })
+        .await
+        .map_err(crate::error::blocking);
+
+        let mut registry = self.registry.write().map_err(crate::error::lock_poisoned)?;
+        let index = match created {
+            Ok(index) => index,
+            Err(err) => {
+                registry.remove(&name);
+                self.registry_storage.store(&registry)?;
+                return Err(err);
+            }
+        };
+        if let Some(entry) = registry.get_mut(&name) {
+            entry.state = IndexState::Created;
+        }
+        self.registry_storage.store(&registry)?;
         self.indices
             .write()
             .map_err(crate::error::lock_poisoned)?
-            .remove(name);
-        let path = self.index_path(name)?;
-        fs::remove_dir_all(path)?;
+            .insert(name, LoadedIndex::Open(index));
         Ok(())
     }
 
-    pub async fn index<'s>(&'s self, name: &str) -> crate::Result<Arc<LocalIndex>> {
-        let index = self
-            .indices
-            .read()
+    pub async fn delete_index(&self, name: &str) -> crate::Result<()> {
+        let path = self.index_path(name)?;
+        let mut registry = self.registry.write().map_err(crate::error::lock_poisoned)?;
+        let entry = match registry.get_mut(name) {
+            Some(entry) => entry,
+            None => return Err(crate::error::index_not_exist(name.to_string())),
+        };
+        // a creating index loaded at startup was interrupted, otherwise it is still being created
+        if entry.state == IndexState::Creating
+            && !self.indices.read().map_err(crate::error::lock_poisoned)?.contains_key(name)
+        {
+            return Err(crate::error::index_being_created(name.to_string()));
+        }
+        // the files go first, the index stays registered and can be deleted again
+        // if they can't be removed. A half created index may have no directory
+        if path.exists() {
+            fs::remove_dir_all(path)?;
+        }
+        registry.remove(name);
+        self.indices
+            .write()
             .map_err(crate::error::lock_poisoned)?
-            .get(name)
-            .cloned();
-        if let Some(index) = index {
-            Ok(index)
-        } else {
-            let path = self.index_path(name)?;
-            // TODO: map index not exist error
-            let index = LocalIndex::open_in_dir(&path, &self.conf)?;
-            let index = Arc::new(index);
+            .remove(name);
+        self.registry_storage.store(&registry)?;
 
-            self.insert_index(name.to_string(), index.clone())?;
+        let mut aliases = self.aliases.write().map_err(crate::error::lock_poisoned)?;
+        aliases.remove_index(name);
+        self.alias_storage.store(&aliases)
+    }
 
-            Ok(index)
+    /// Returns the index `name`, aliases must be resolved before
+    pub async fn index(&self, name: &str) -> crate::Result<Arc<LocalIndex>> {
+        let name = name.to_string();
+        match self.indices.read().map_err(crate::error::lock_poisoned)?.get(&name) {
+            Some(LoadedIndex::Open(index)) => Ok(index.clone()),
+            Some(LoadedIndex::Failed(reason)) => {
+                Err(crate::error::index_unavailable(name, reason.clone()))
+            }
+            None => Err(crate::error::index_not_exist(name)),
         }
     }
 
-    fn insert_index(&self, name: String, index: Arc<LocalIndex>) -> crate::Result<()> {
-        self.indices
-            .write()
-            .map_err(crate::error::lock_poisoned)?
-            .insert(name, index);
+    /// Open indices sorted by name
+    pub fn open_indices(&self) -> crate::Result<Vec<NamedIndex>> {
+        let indices = self.indices.read().map_err(crate::error::lock_poisoned)?;
+        let mut open = indices
+            .iter()
+            .filter_map(|(name, index)| match index {
+                LoadedIndex::Open(index) => Some((name.clone(), index.clone())),
+                LoadedIndex::Failed(_) => None,
+            })
+            .collect::<Vec<_>>();
+        open.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(open)
+    }
+
+    /// Every registered index with its status, sorted by name
+    pub fn list_indices(&self) -> crate::Result<Vec<IndexInfo>> {
+        let registry = self.registry.read().map_err(crate::error::lock_poisoned)?;
+        let indices = self.indices.read().map_err(crate::error::lock_poisoned)?;
+        Ok(registry
+            .iter()
+            .map(|(name, entry)| {
+                let (status, error) = match indices.get(name) {
+                    Some(LoadedIndex::Open(_)) => (IndexStatus::Open, None),
+                    Some(LoadedIndex::Failed(reason)) => (IndexStatus::Failed, Some(reason.clone())),
+                    None => (IndexStatus::Failed, Some("Index is not loaded".to_string())),
+                };
+                IndexInfo {
+                    name: name.clone(),
+                    entry: entry.clone(),
+                    status,
+                    error,
+                }
+            })
+            .collect())
+    }
+
+    /// Name of the index writes through `name` go to, `name` itself if it is not an alias
+    pub fn write_index(&self, name: &str) -> crate::Result<String> {
+        let aliases = self.aliases.read().map_err(crate::error::lock_poisoned)?;
+        Ok(aliases.write_target(name)?.unwrap_or(name).to_string())
+    }
+
+    /// Name of the index `name` stands for, fails for aliases of several indices
+    pub fn single_index(&self, name: &str) -> crate::Result<String> {
+        let aliases = self.aliases.read().map_err(crate::error::lock_poisoned)?;
+        Ok(aliases.single_target(name)?.unwrap_or(name).to_string())
+    }
+
+    pub fn aliases(&self) -> crate::Result<Aliases> {
+        Ok(self.aliases.read().map_err(crate::error::lock_poisoned)?.clone())
+    }
+
+    /// Applies every action or none of them
+    pub fn update_aliases(&self, req: AliasActionsReq) -> crate::Result<()> {
+        let registry = self.registry.read().map_err(crate::error::lock_poisoned)?;
+        let mut aliases = self.aliases.write().map_err(crate::error::lock_poisoned)?;
+        let mut updated = aliases.clone();
+        for action in req.actions {
+            updated.apply(action)?;
+        }
+        for (name, alias) in updated.iter() {
+            self.index_path(name)?;
+            if registry.contains(name) {
+                return Err(crate::error::name_already_used(name.clone()));
+            }
+            for index in &alias.indices {
+                if !registry.contains(index) {
+                    return Err(crate::error::index_not_exist(index.clone()));
+                }
+            }
+        }
+        self.alias_storage.store(&updated)?;
+        *aliases = updated;
         Ok(())
     }
 
+    /// Indices matching a comma separated list of names, aliases and wildcard patterns
+    /// of index names
+    pub fn resolve(&self, expr: &str) -> crate::Result<Vec<ResolvedIndex>> {
+        let registry = self.registry.read().map_err(crate::error::lock_poisoned)?;
+        let aliases = self.aliases.read().map_err(crate::error::lock_poisoned)?;
+        let mut resolved: Vec<ResolvedIndex> = Vec::new();
+        for pattern in expr.split(',').map(str::trim).filter(|pattern| !pattern.is_empty()) {
+            let (matched, explicit) = match Wildcard::new(pattern) {
+                Wildcard::Exact(name) => match aliases.get(&name) {
+                    Some(alias) => (alias.indices.clone(), false),
+                    None => (vec![name], true),
+                },
+                wildcard => {
+                    let matched = registry
+                        .names()
+                        .filter(|name| wildcard.matches(name))
+                        .cloned()
+                        .collect();
+                    (matched, false)
+                }
+            };
+            for name in matched {
+                match resolved.iter_mut().find(|index| index.name == name) {
+                    Some(index) => index.explicit |= explicit,
+                    None => resolved.push(ResolvedIndex { name, explicit }),
+                }
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Open indices among the resolved ones, and the names of the failed ones which are
+    /// skipped as they were only matched by an alias or a wildcard
+    pub fn resolved_indices(
+        &self,
+        resolved: Vec<ResolvedIndex>,
+    ) -> crate::Result<(Vec<NamedIndex>, Vec<String>)> {
+        let indices = self.indices.read().map_err(crate::error::lock_poisoned)?;
+        let mut open = Vec::with_capacity(resolved.len());
+        let mut skipped = Vec::new();
+        for ResolvedIndex { name, explicit } in resolved {
+            match indices.get(&name) {
+                Some(LoadedIndex::Open(index)) => open.push((name, index.clone())),
+                Some(LoadedIndex::Failed(_)) if !explicit => skipped.push(name),
+                Some(LoadedIndex::Failed(reason)) => {
+                    return Err(crate::error::index_unavailable(name, reason.clone()))
+                }
+                // a registered index is not loaded until its creation completes
+                None if !explicit => {}
+                None => return Err(crate::error::index_not_exist(name)),
+            }
+        }
+        Ok((open, skipped))
+    }
+
     fn index_path(&self, name: &str) -> crate::Result<PathBuf> {
-        if !name
-            .chars()
-            .all(|c: char| c.is_ascii_alphanumeric() || c == '_')
-        {
+        if !is_valid_name(name) {
             Err(crate::error::invalid_index_name(name.to_string()))
         } else {
             Ok(self.conf.data_dir.join(name))
         }
     }
 }
+
+/// The synonyms dir shares the data dir with the indices
+fn is_valid_name(name: &str) -> bool {
+    name != SYNONYMS_DIR && name.chars().all(|c: char| c.is_ascii_alphanumeric() || c == '_')
+}
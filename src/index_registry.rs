@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use tantivy::chrono::{DateTime, Utc};
+
+use crate::index_config::IndexConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexState {
+    /// the index files may be incomplete until it is created
+    Creating,
+    Created,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// RFC 3339 creation time, unknown for indices found on disk on file systems without birth times
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    /// hash of the config the index was created with, unknown for indices found on disk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_hash: Option<String>,
+    pub state: IndexState,
+}
+
+impl IndexEntry {
+    pub fn creating(index_conf: &IndexConfig) -> crate::Result<Self> {
+        Ok(Self {
+            created: Some(Utc::now().to_rfc3339()),
+            config_hash: Some(config_hash(index_conf)?),
+            state: IndexState::Creating,
+        })
+    }
+
+    /// Entry of an index created before the registry existed, `created` is the birth time of its dir
+    pub fn found(created: Option<SystemTime>) -> Self {
+        Self {
+            created: created.map(|time| DateTime::<Utc>::from(time).to_rfc3339()),
+            config_hash: None,
+            state: IndexState::Created,
+        }
+    }
+}
+
+/// Indices by name
+#[derive(Default, Serialize, Deserialize)]
+pub struct IndexRegistry(BTreeMap<String, IndexEntry>);
+
+impl IndexRegistry {
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut IndexEntry> {
+        self.0.get_mut(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    pub fn insert(&mut self, name: String, entry: IndexEntry) {
+        self.0.insert(name, entry);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<IndexEntry> {
+        self.0.remove(name)
+    }
+
+    /// Sorted names
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.0.keys()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &IndexEntry)> {
+        self.0.iter()
+    }
+}
+
+/// FNV-1a of the JSON config, stable across builds unlike the std hasher
+fn config_hash(index_conf: &IndexConfig) -> crate::Result<String> {
+    let hash = serde_json::to_vec(index_conf)?
+        .into_iter()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    Ok(format!("{:016x}", hash))
+}
@@ -1,28 +1,42 @@
+mod aggregation;
+mod aliases;
 mod api;
+mod commit_scheduler;
 mod config;
 mod dto;
 mod error;
+mod fast_field;
+mod highlight;
 mod index;
 mod index_config;
 mod index_manager;
+mod index_registry;
+mod merge_policy;
+mod multi_search;
+mod point_in_time;
 mod query;
 mod security;
+mod sort;
+mod source;
+mod stop_words;
+mod synonyms;
+mod tokenizers;
+mod tasks;
 mod utils;
 
 use crate::config::AppConfig;
 use crate::index_manager::IndexManager;
 use crate::security::{authc::AuthService, authz::PermissionsStorage};
+use crate::tasks::Tasks;
 
 pub use crate::error::Error;
 pub type Result<T, E = crate::error::Error> = std::result::Result<T, E>;
 
 /*
     TODO:
-    commit каждую секунду
     тестирование - setup/teardown
     ленивая инициализация IndexReader и IndexWriter
     разделение Scheme и LocalIndex - Scheme может храниться даже если самого индекса на этой ноде нет.
-    primary key ?
     кластер: шардинг, репликация
     шифрование трафика api
     шифрование трафика кластера
@@ -33,6 +47,7 @@ pub struct AppState {
     pub indices: IndexManager,
     pub auth: AuthService,
     pub access_control: PermissionsStorage,
+    pub tasks: Tasks,
 }
 
 impl AppState {
@@ -45,6 +60,7 @@ impl AppState {
             indices: IndexManager::new(search_conf)?,
             auth: authc,
             access_control: authz,
+            tasks: Tasks::default(),
         })
     }
 }
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use tantivy::merge_policy::{LogMergePolicy, MergePolicy};
+use tantivy::{SegmentId, SegmentMeta};
+
+fn default_min_num_segments() -> usize {
+    8
+}
+
+fn default_max_docs_before_merge() -> usize {
+    10_000_000
+}
+
+fn default_min_layer_size() -> u32 {
+    10_000
+}
+
+fn default_level_log_size() -> f64 {
+    0.75
+}
+
+/// Parameters of tantivy's `LogMergePolicy`, the defaults are tantivy's ones
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergePolicyConfig {
+    /// minimum number of segments of a layer to merge them
+    #[serde(default = "default_min_num_segments")]
+    pub min_num_segments: usize,
+    /// max layer size, larger segments are only merged by a force merge
+    #[serde(default = "default_max_docs_before_merge")]
+    pub max_docs_before_merge: usize,
+    /// min layer size, smaller segments all belong to the first layer
+    #[serde(default = "default_min_layer_size")]
+    pub min_layer_size: u32,
+    /// log2 of the size ratio between two consecutive layers
+    #[serde(default = "default_level_log_size")]
+    pub level_log_size: f64,
+    /// segments with a higher ratio of deleted docs are rewritten after a commit,
+    /// which tantivy's policy doesn't do as it ignores deletes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub del_docs_ratio_before_merge: Option<f32>,
+}
+
+impl Default for MergePolicyConfig {
+    fn default() -> Self {
+        Self {
+            min_num_segments: default_min_num_segments(),
+            max_docs_before_merge: default_max_docs_before_merge(),
+            min_layer_size: default_min_layer_size(),
+            level_log_size: default_level_log_size(),
+            del_docs_ratio_before_merge: None,
+        }
+    }
+}
+
+impl MergePolicyConfig {
+    pub fn validate(&self) -> crate::Result<()> {
+        // a layer of one segment would be merged again and again
+        if self.min_num_segments < 2 {
+            return Err(crate::error::invalid_settings("min_num_segments must be at least 2".to_string()));
+        }
+        if self.level_log_size <= 0.0 {
+            return Err(crate::error::invalid_settings("level_log_size must be positive".to_string()));
+        }
+        match self.del_docs_ratio_before_merge {
+            Some(ratio) if !(ratio > 0.0 && ratio <= 1.0) => Err(crate::error::invalid_settings(
+                "del_docs_ratio_before_merge must be in ]0, 1]".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn merge_policy(&self) -> Box<dyn MergePolicy> {
+        let mut policy = LogMergePolicy::default();
+        policy.set_min_num_segments(self.min_num_segments);
+        policy.set_max_docs_before_merge(self.max_docs_before_merge);
+        policy.set_min_layer_size(self.min_layer_size);
+        policy.set_level_log_size(self.level_log_size);
+        Box::new(policy)
+    }
+
+    /// Segments to rewrite alone to reclaim their deleted docs
+    pub fn segments_to_expunge(&self, metas: &[SegmentMeta]) -> Vec<SegmentId> {
+        let ratio = match self.del_docs_ratio_before_merge {
+            Some(ratio) => ratio,
+            None => return Vec::new(),
+        };
+        metas
+            .iter()
+            .filter(|meta| {
+                meta.has_deletes()
+                    && meta.num_deleted_docs() as f32 / meta.max_doc() as f32 > ratio
+            })
+            .map(SegmentMeta::id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let config: MergePolicyConfig =
+            serde_json::from_str(r#"{"min_num_segments": 4, "del_docs_ratio_before_merge": 0.3}"#).unwrap();
+        assert_eq!(config.min_num_segments, 4);
+        assert_eq!(config.max_docs_before_merge, 10_000_000);
+        assert!(config.validate().is_ok());
+
+        let config = MergePolicyConfig { min_num_segments: 1, ..Default::default() };
+        assert!(config.validate().is_err());
+        let config = MergePolicyConfig { del_docs_ratio_before_merge: Some(1.5), ..Default::default() };
+        assert!(config.validate().is_err());
+        let config = MergePolicyConfig { del_docs_ratio_before_merge: Some(0.0), ..Default::default() };
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use futures::future::try_join_all;
+
+use crate::dto::{CountReq, CountResp, ScoredDocument, SearchReq, SearchResp};
+use crate::index::LocalIndex;
+use crate::sort::SortPosition;
+
+struct Hit {
+    doc: ScoredDocument,
+    position: Option<SortPosition>,
+    /// index ordinal and rank of the document in the results of its index
+    order: (usize, usize),
+}
+
+impl Hit {
+    /// By sort position when sorting, else by descending score
+    fn cmp(&self, other: &Hit) -> Ordering {
+        match (&self.position, &other.position) {
+            (Some(position), Some(other)) => position.keys.cmp(&other.keys),
+            _ => other.doc.score.partial_cmp(&self.doc.score).unwrap_or(Ordering::Equal),
+        }
+        .then(self.order.cmp(&other.order))
+    }
+}
+
+/// Sums the counts of every index
+pub async fn count(indices: Vec<Arc<LocalIndex>>, req: CountReq) -> crate::Result<CountResp> {
+    if req.pit.is_some() {
+        return Err(crate::error::invalid_query(
+            "Point in time needs a single index".to_string(),
+        ));
+    }
+    let counts = try_join_all(indices.iter().map(|index| {
+        index.count(CountReq {
+            query: req.query.clone(),
+            default_fields: req.default_fields.clone(),
+            default_operator: req.default_operator,
+            pit: None,
+        })
+    }))
+    .await?;
+    Ok(CountResp {
+        count: counts.iter().map(|resp| resp.count).sum(),
+        skipped_indices: Vec::new(),
+    })
+}
+
+/// Searches every index for its `offset + limit` top documents and merges them
+pub async fn search(
+    indices: Vec<(String, Arc<LocalIndex>)>,
+    req: SearchReq,
+) -> crate::Result<SearchResp> {
+    if req.aggs.is_some() || req.pit.is_some() || req.search_after.is_some() {
+        return Err(crate::error::invalid_query(
+            "Aggregations, point in time and search_after need a single index".to_string(),
+        ));
+    }
+    // the raw sort values of different types don't compare
+    if let Some(sort) = req.sort.as_deref().filter(|sort| !sort.is_empty()) {
+        let mut expected = None;
+        for (name, index) in &indices {
+            let types = index.sort_types(sort)?;
+            match &expected {
+                None => expected = Some((name, types)),
+                Some((first, first_types)) => {
+                    if let Some(pos) = types.iter().zip(first_types).position(|(a, b)| a != b) {
+                        return Err(crate::error::invalid_query(format!(
+                            "Sort field '{}' has different types in indices '{}' and '{}'",
+                            sort[pos].field, first, name
+                        )));
+                    }
+                }
+            }
+        }
+    }
+    let results = try_join_all(indices.iter().map(|(_, index)| {
+        index.search_with_positions(SearchReq {
+            query: req.query.clone(),
+            default_fields: req.default_fields.clone(),
+            default_operator: req.default_operator,
+            limit: req.offset + req.limit,
+            offset: 0,
+            highlight: req.highlight.clone(),
+            aggs: None,
+            sort: req.sort.clone(),
+            search_after: None,
+            pit: None,
+            total_hits: req.total_hits,
+            explain: req.explain,
+            source: req.source.clone(),
+        })
+    }))
+    .await?;
+
+    let mut total_hits = req.total_hits.then_some(0);
+    let mut hits = Vec::new();
+    for (index_ord, ((name, _), (resp, positions))) in indices.iter().zip(results).enumerate() {
+        total_hits = total_hits.zip(resp.total_hits).map(|(total, hits)| total + hits);
+        // positions are in the order of the documents, when sorting by fields
+        let mut positions = positions.into_iter();
+        for (rank, mut doc) in resp.docs.into_iter().enumerate() {
+            doc.index = Some(name.clone());
+            // a cursor is only meaningful within its index
+            doc.cursor = None;
+            hits.push(Hit {
+                doc,
+                position: positions.next(),
+                order: (index_ord, rank),
+            });
+        }
+    }
+    hits.sort_by(Hit::cmp);
+
+    let docs = hits
+        .into_iter()
+        .skip(req.offset)
+        .take(req.limit)
+        .map(|hit| hit.doc)
+        .collect();
+    Ok(SearchResp {
+        docs,
+        total_hits,
+        aggs: None,
+        skipped_indices: Vec::new(),
+    })
+}
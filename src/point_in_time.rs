@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tantivy::{Index, IndexReader, LeasedItem, ReloadPolicy, Searcher};
+
+/// open points in time of an index, each one keeps its segments on disk
+const MAX_POINT_IN_TIMES: usize = 1000;
+const MAX_KEEP_ALIVE: Duration = Duration::from_secs(24 * 60 * 60);
+/// interval at which expired points in time release their segments
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+struct PointInTime {
+    /// never reloaded, so it keeps the segments of the generation it was opened at
+    reader: IndexReader,
+    keep_alive: Duration,
+    expires_at: Instant,
+}
+
+/// Searchers pinned to an index generation, each one lives while it is used within its keep alive
+#[derive(Default)]
+pub struct PointInTimes {
+    pits: Mutex<HashMap<String, PointInTime>>,
+    next_id: AtomicU64,
+}
+
+impl PointInTimes {
+    /// Removes the expired points in time in the background until `self` is dropped,
+    /// so that their segments aren't kept until the next request
+    pub fn spawn_sweeper(self: &Arc<Self>) -> crate::Result<()> {
+        let pits = Arc::downgrade(self);
+        std::thread::Builder::new()
+            .name("pit-sweeper".to_string())
+            .spawn(move || loop {
+                std::thread::sleep(SWEEP_INTERVAL);
+                let this = match pits.upgrade() {
+                    Some(this) => this,
+                    None => break,
+                };
+                if let Ok(mut pits) = this.pits.lock() {
+                    Self::remove_expired(&mut pits);
+                };
+            })?;
+        Ok(())
+    }
+
+    /// Pins the last committed generation of `index` and returns its id
+    pub fn open(&self, index: &Index, keep_alive: Duration) -> crate::Result<String> {
+        if keep_alive > MAX_KEEP_ALIVE {
+            return Err(crate::error::invalid_query(format!(
+                "Point in time keep alive must be at most {} seconds",
+                MAX_KEEP_ALIVE.as_secs()
+            )));
+        }
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .num_searchers(1)
+            .try_into()?;
+        let id = self.next_id();
+        let mut pits = self.pits.lock().map_err(crate::error::lock_poisoned)?;
+        Self::remove_expired(&mut pits);
+        if pits.len() >= MAX_POINT_IN_TIMES {
+            return Err(crate::error::too_many_point_in_times(MAX_POINT_IN_TIMES));
+        }
+        pits.insert(
+            id.clone(),
+            PointInTime {
+                reader,
+                keep_alive,
+                expires_at: Instant::now() + keep_alive,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Returns a searcher of the point in time and extends its keep alive
+    pub fn searcher(&self, id: &str) -> crate::Result<LeasedItem<Searcher>> {
+        let reader = {
+            let mut pits = self.pits.lock().map_err(crate::error::lock_poisoned)?;
+            Self::remove_expired(&mut pits);
+            let pit = pits
+                .get_mut(id)
+                .ok_or_else(|| crate::error::point_in_time_not_found(id.to_string()))?;
+            pit.expires_at = Instant::now() + pit.keep_alive;
+            pit.reader.clone()
+        };
+        Ok(reader.searcher())
+    }
+
+    /// Returns false if the point in time does not exist or has already expired
+    pub fn close(&self, id: &str) -> crate::Result<bool> {
+        let mut pits = self.pits.lock().map_err(crate::error::lock_poisoned)?;
+        Self::remove_expired(&mut pits);
+        Ok(pits.remove(id).is_some())
+    }
+
+    fn remove_expired(pits: &mut HashMap<String, PointInTime>) {
+        let now = Instant::now();
+        pits.retain(|_, pit| pit.expires_at > now);
+    }
+
+    /// Ids are unique across restarts as they start with the creation time
+    fn next_id(&self) -> String {
+        let counter = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        format!("{:x}{:08x}", created, counter)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tantivy::schema::Schema;
+
+    #[test]
+    fn test_limits() {
+        let index = Index::create_in_ram(Schema::builder().build());
+        let pits = PointInTimes::default();
+        assert!(pits.open(&index, MAX_KEEP_ALIVE + Duration::from_secs(1)).is_err());
+        for _ in 0..MAX_POINT_IN_TIMES {
+            pits.open(&index, Duration::from_secs(60)).unwrap();
+        }
+        assert!(pits.open(&index, Duration::from_secs(60)).is_err());
+
+        // expired ones no longer count
+        let pits = PointInTimes::default();
+        for _ in 0..MAX_POINT_IN_TIMES {
+            pits.open(&index, Duration::from_secs(0)).unwrap();
+        }
+        assert!(pits.open(&index, Duration::from_secs(60)).is_ok());
+    }
+}
@@ -1,5 +1,19 @@
+use std::borrow::Cow;
+use std::ops::Bound;
+
+use serde::Deserialize;
 use tantivy::chrono;
-use tantivy::schema::{Field, FieldType, Term};
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, EmptyQuery, FuzzyTermQuery, Occur, PhraseQuery, Query,
+    RangeQuery, RegexQuery, TermQuery,
+};
+use tantivy::schema::{Facet, Field, FieldType, IndexRecordOption, Schema, Term, Value};
+use tantivy::tokenizer::{TextAnalyzer, TokenizerManager};
+use tantivy::Score;
+use tantivy_query_grammar::{UserInputAst, UserInputBound, UserInputLeaf, UserInputLiteral};
+
+/// max number of phrases a phrase with alternative tokens, like synonyms, expands to
+const MAX_PHRASE_EXPANSIONS: usize = 64;
 
 pub fn make_term(field: Field, field_type: &FieldType, value: &str) -> crate::Result<Term> {
     Ok(match field_type {
@@ -22,6 +36,671 @@ pub fn make_term(field: Field, field_type: &FieldType, value: &str) -> crate::Re
         FieldType::Bytes(_) => Term::from_field_bytes(
             field,
             &base64::decode(value).map_err(crate::error::value_parsing_err)?),
-        FieldType::HierarchicalFacet(_) => todo!(),
+        FieldType::HierarchicalFacet(_) => Term::from_facet(
+            field,
+            &Facet::from_text(value).map_err(crate::error::value_parsing_err)?),
+    })
+}
+
+pub fn value_to_term(field: Field, value: &Value) -> Option<Term> {
+    Some(match value {
+        Value::Str(text) => Term::from_field_text(field, text),
+        Value::PreTokStr(_) => return None,
+        Value::U64(val) => Term::from_field_u64(field, *val),
+        Value::I64(val) => Term::from_field_i64(field, *val),
+        Value::F64(val) => Term::from_field_f64(field, *val),
+        Value::Date(date) => Term::from_field_date(field, date),
+        Value::Facet(facet) => Term::from_facet(field, facet),
+        Value::Bytes(bytes) => Term::from_field_bytes(field, bytes),
     })
 }
+
+/// Term value in the query DSL, numbers may be passed as JSON numbers
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum DslValue {
+    Str(String),
+    Json(serde_json::Value),
+}
+
+impl DslValue {
+    pub fn as_str(&self) -> Cow<'_, str> {
+        match self {
+            DslValue::Str(s) => Cow::Borrowed(s),
+            DslValue::Json(v) => Cow::Owned(v.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct BoolDsl {
+    #[serde(default)]
+    pub must: Vec<QueryDsl>,
+    #[serde(default)]
+    pub should: Vec<QueryDsl>,
+    #[serde(default)]
+    pub must_not: Vec<QueryDsl>,
+    /// like `must`, but doesn't affect the score
+    #[serde(default)]
+    pub filter: Vec<QueryDsl>,
+}
+
+/// The value is not analyzed, `phrase` matches analyzed text
+#[derive(Debug, Clone, Deserialize)]
+pub struct TermDsl {
+    pub field: String,
+    pub value: DslValue,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TermsDsl {
+    pub field: String,
+    pub values: Vec<DslValue>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RangeDsl {
+    pub field: String,
+    pub gt: Option<DslValue>,
+    pub gte: Option<DslValue>,
+    pub lt: Option<DslValue>,
+    pub lte: Option<DslValue>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PhraseDsl {
+    pub field: String,
+    pub text: String,
+}
+
+fn default_fuzzy_distance() -> u8 {
+    1
+}
+
+fn default_transposition() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FuzzyDsl {
+    pub field: String,
+    pub value: String,
+    #[serde(default = "default_fuzzy_distance")]
+    pub distance: u8,
+    #[serde(default = "default_transposition")]
+    pub transposition: bool,
+    /// match terms starting with a fuzzy match of `value`
+    #[serde(default)]
+    pub prefix: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegexDsl {
+    pub field: String,
+    pub pattern: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExistsDsl {
+    pub field: String,
+}
+
+/// Structured query, an alternative to the query parser syntax
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryDsl {
+    Bool(BoolDsl),
+    Term(TermDsl),
+    Terms(TermsDsl),
+    Range(RangeDsl),
+    Phrase(PhraseDsl),
+    Prefix(TermDsl),
+    Fuzzy(FuzzyDsl),
+    Regex(RegexDsl),
+    Exists(ExistsDsl),
+    MatchAll {},
+}
+
+impl QueryDsl {
+    /// Text is analyzed by the analyzers of `tokenizers`, which are the search ones
+    pub fn compile(&self, schema: &Schema, tokenizers: &TokenizerManager) -> crate::Result<Box<dyn Query>> {
+        Ok(match self {
+            QueryDsl::Bool(BoolDsl { must, should, must_not, filter }) => {
+                let mut clauses = Vec::new();
+                for query in must {
+                    clauses.push((Occur::Must, query.compile(schema, tokenizers)?));
+                }
+                for query in should {
+                    clauses.push((Occur::Should, query.compile(schema, tokenizers)?));
+                }
+                for query in must_not {
+                    clauses.push((Occur::MustNot, query.compile(schema, tokenizers)?));
+                }
+                for query in filter {
+                    let query = BoostQuery::new(query.compile(schema, tokenizers)?, 0.0);
+                    clauses.push((Occur::Must, Box::new(query)));
+                }
+                Box::new(BooleanQuery::new(clauses))
+            }
+            QueryDsl::Term(TermDsl { field, value }) => {
+                let term = make_field_term(schema, field, &value.as_str())?;
+                Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs))
+            }
+            QueryDsl::Terms(TermsDsl { field, values }) => {
+                let terms = values
+                    .iter()
+                    .map(|value| make_field_term(schema, field, &value.as_str()))
+                    .collect::<crate::Result<Vec<_>>>()?;
+                Box::new(BooleanQuery::new_multiterms_query(terms))
+            }
+            QueryDsl::Range(RangeDsl { field: field_name, gt, gte, lt, lte }) => {
+                let field = get_field(schema, field_name)?;
+                let bound = |excluded: &Option<DslValue>, included: &Option<DslValue>| {
+                    Ok(match (excluded, included) {
+                        (Some(_), Some(_)) => {
+                            return Err(crate::error::invalid_query(format!(
+                                "Range on '{}' has both exclusive and inclusive bounds",
+                                field_name
+                            )))
+                        }
+                        (Some(value), None) => {
+                            Bound::Excluded(make_field_term(schema, field_name, &value.as_str())?)
+                        }
+                        (None, Some(value)) => {
+                            Bound::Included(make_field_term(schema, field_name, &value.as_str())?)
+                        }
+                        (None, None) => Bound::Unbounded,
+                    })
+                };
+                let left = bound(gt, gte)?;
+                let right = bound(lt, lte)?;
+                let value_type = schema.get_field_entry(field).field_type().value_type();
+                Box::new(RangeQuery::new_term_bounds(field, value_type, &left, &right))
+            }
+            QueryDsl::Phrase(PhraseDsl { field: field_name, text }) => {
+                let field = get_text_field(schema, field_name)?;
+                let analyzer = get_analyzer(schema, field, tokenizers)?;
+                phrase_query(field, &analyzer, text)?.unwrap_or_else(|| Box::new(EmptyQuery))
+            }
+            QueryDsl::Prefix(TermDsl { field: field_name, value }) => {
+                let field = get_text_field(schema, field_name)?;
+                let pattern = format!("{}.*", regex::escape(&value.as_str()));
+                Box::new(RegexQuery::from_pattern(&pattern, field)?)
+            }
+            QueryDsl::Fuzzy(FuzzyDsl { field: field_name, value, distance, transposition, prefix }) => {
+                let field = get_text_field(schema, field_name)?;
+                let term = Term::from_field_text(field, value);
+                if *prefix {
+                    Box::new(FuzzyTermQuery::new_prefix(term, *distance, *transposition))
+                } else {
+                    Box::new(FuzzyTermQuery::new(term, *distance, *transposition))
+                }
+            }
+            QueryDsl::Regex(RegexDsl { field: field_name, pattern }) => {
+                let field = get_text_field(schema, field_name)?;
+                Box::new(
+                    RegexQuery::from_pattern(pattern, field)
+                        .map_err(crate::error::value_parsing_err)?,
+                )
+            }
+            QueryDsl::Exists(ExistsDsl { field: field_name }) => {
+                // an unbounded range matches every document having any term in the field
+                let field = get_field(schema, field_name)?;
+                let field_entry = schema.get_field_entry(field);
+                if !field_entry.is_indexed() {
+                    return Err(crate::error::field_not_indexed(field_name.clone()));
+                }
+                let value_type = field_entry.field_type().value_type();
+                Box::new(RangeQuery::new_term_bounds(
+                    field,
+                    value_type,
+                    &Bound::Unbounded,
+                    &Bound::Unbounded,
+                ))
+            }
+            QueryDsl::MatchAll {} => Box::new(AllQuery),
+        })
+    }
+}
+
+fn get_field(schema: &Schema, field_name: &str) -> crate::Result<Field> {
+    schema
+        .get_field(field_name)
+        .ok_or_else(|| crate::error::field_not_exist(field_name.to_string()))
+}
+
+fn get_text_field(schema: &Schema, field_name: &str) -> crate::Result<Field> {
+    let field = get_field(schema, field_name)?;
+    match schema.get_field_entry(field).field_type() {
+        FieldType::Str(_) => Ok(field),
+        _ => Err(crate::error::invalid_query(format!(
+            "Field '{}' is not a text field",
+            field_name
+        ))),
+    }
+}
+
+fn get_analyzer(schema: &Schema, field: Field, tokenizers: &TokenizerManager) -> crate::Result<TextAnalyzer> {
+    let field_entry = schema.get_field_entry(field);
+    let indexing = match field_entry.field_type() {
+        FieldType::Str(options) => options.get_indexing_options(),
+        _ => None,
+    };
+    let indexing = indexing.ok_or_else(|| crate::error::field_not_indexed(field_entry.name().to_string()))?;
+    tokenizers
+        .get(indexing.tokenizer())
+        .ok_or_else(|| crate::error::analyzer_not_exist(indexing.tokenizer().to_string()))
+}
+
+/// Terms of the tokens of `text` grouped by position
+fn analyze(field: Field, analyzer: &TextAnalyzer, text: &str) -> Vec<(usize, Vec<Term>)> {
+    let mut positions: Vec<(usize, Vec<Term>)> = Vec::new();
+    analyzer.token_stream(text).process(&mut |token| {
+        let term = Term::from_field_text(field, &token.text);
+        match positions.last_mut() {
+            Some((position, terms)) if *position == token.position => terms.push(term),
+            _ => positions.push((token.position, vec![term])),
+        }
+    });
+    positions
+}
+
+/// Tokens at the same position, like synonyms, are alternatives: the phrase
+/// matches if one of its combinations does. None if `text` has no token
+fn phrase_query(field: Field, analyzer: &TextAnalyzer, text: &str) -> crate::Result<Option<Box<dyn Query>>> {
+    let positions = analyze(field, analyzer, text);
+    let expansions = positions
+        .iter()
+        .try_fold(1usize, |expansions, (_, terms)| expansions.checked_mul(terms.len()))
+        .filter(|expansions| *expansions <= MAX_PHRASE_EXPANSIONS);
+    if expansions.is_none() {
+        return Err(crate::error::too_many_expansions(MAX_PHRASE_EXPANSIONS));
+    }
+    let mut phrases = vec![Vec::new()];
+    for (position, terms) in positions {
+        phrases = phrases
+            .iter()
+            .flat_map(|phrase: &Vec<(usize, Term)>| {
+                terms.iter().map(move |term| {
+                    let mut phrase = phrase.clone();
+                    phrase.push((position, term.clone()));
+                    phrase
+                })
+            })
+            .collect();
+    }
+    let queries = phrases
+        .into_iter()
+        .filter_map(|mut terms| -> Option<Box<dyn Query>> {
+            match terms.len() {
+                0 => None,
+                1 => {
+                    let (_, term) = terms.remove(0);
+                    Some(Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs)))
+                }
+                _ => Some(Box::new(PhraseQuery::new_with_offset(terms))),
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(union(queries))
+}
+
+fn union(mut queries: Vec<Box<dyn Query>>) -> Option<Box<dyn Query>> {
+    match queries.len() {
+        0 => None,
+        1 => Some(queries.remove(0)),
+        _ => Some(Box::new(BooleanQuery::union(queries))),
+    }
+}
+
+fn boosted(query: Box<dyn Query>, boost: Option<Score>) -> Box<dyn Query> {
+    match boost {
+        Some(boost) => Box::new(BoostQuery::new(query, boost)),
+        None => query,
+    }
+}
+
+/// Query parser syntax, the text is analyzed like in the DSL: the tantivy parser
+/// makes a phrase of the tokens at the same position, so synonyms never matched
+pub struct QueryStringParser<'a> {
+    schema: &'a Schema,
+    tokenizers: &'a TokenizerManager,
+    default_fields: Vec<(Field, Option<Score>)>,
+    default_occur: Occur,
+}
+
+impl<'a> QueryStringParser<'a> {
+    /// Text is analyzed by the analyzers of `tokenizers`, which are the search ones
+    pub fn new(
+        schema: &'a Schema,
+        tokenizers: &'a TokenizerManager,
+        default_fields: Vec<(Field, Option<Score>)>,
+        conjunction_by_default: bool,
+    ) -> Self {
+        Self {
+            schema,
+            tokenizers,
+            default_fields,
+            default_occur: if conjunction_by_default { Occur::Must } else { Occur::Should },
+        }
+    }
+
+    pub fn parse(&self, query: &str) -> crate::Result<Box<dyn Query>> {
+        let ast = tantivy_query_grammar::parse_query(query)
+            .map_err(|_| crate::error::invalid_query(format!("Syntax error in query '{}'", query)))?;
+        if self.all_negative(&ast) {
+            return Err(crate::error::invalid_query(
+                "Query only excludes documents, it needs a clause to match some".to_string(),
+            ));
+        }
+        Ok(self.convert(ast)?.unwrap_or_else(|| Box::new(EmptyQuery)))
+    }
+
+    fn all_negative(&self, ast: &UserInputAst) -> bool {
+        match ast {
+            UserInputAst::Clause(clauses) => {
+                !clauses.is_empty()
+                    && clauses.iter().all(|(occur, ast)| {
+                        occur.unwrap_or(self.default_occur) == Occur::MustNot || self.all_negative(ast)
+                    })
+            }
+            UserInputAst::Leaf(_) => false,
+            UserInputAst::Boost(ast, _) => self.all_negative(ast),
+        }
+    }
+
+    /// None for text without tokens, e.g. only stop words, which its clause ignores
+    fn convert(&self, ast: UserInputAst) -> crate::Result<Option<Box<dyn Query>>> {
+        Ok(match ast {
+            UserInputAst::Clause(clauses) => {
+                let mut queries = Vec::new();
+                for (occur, ast) in clauses {
+                    if let Some(query) = self.convert(ast)? {
+                        queries.push((occur.unwrap_or(self.default_occur), query));
+                    }
+                }
+                if queries.is_empty() {
+                    None
+                } else {
+                    Some(Box::new(BooleanQuery::new(queries)))
+                }
+            }
+            UserInputAst::Boost(ast, boost) => self
+                .convert(*ast)?
+                .map(|query| boosted(query, Some(boost as Score))),
+            UserInputAst::Leaf(leaf) => match *leaf {
+                UserInputLeaf::All => Some(Box::new(AllQuery)),
+                UserInputLeaf::Literal(UserInputLiteral { field_name, phrase }) => {
+                    let mut queries = Vec::new();
+                    for (field, boost) in self.fields(field_name)? {
+                        if let Some(query) = self.literal_query(field, &phrase)? {
+                            queries.push(boosted(query, boost));
+                        }
+                    }
+                    union(queries)
+                }
+                UserInputLeaf::Range { field, lower, upper } => {
+                    let mut queries = Vec::new();
+                    for (field, boost) in self.fields(field)? {
+                        let value_type = self.schema.get_field_entry(field).field_type().value_type();
+                        let query = RangeQuery::new_term_bounds(
+                            field,
+                            value_type,
+                            &self.bound(field, &lower)?,
+                            &self.bound(field, &upper)?,
+                        );
+                        queries.push(boosted(Box::new(query), boost));
+                    }
+                    union(queries)
+                }
+            },
+        })
+    }
+
+    /// The field named in the query or the default ones, with their boost
+    fn fields(&self, field_name: Option<String>) -> crate::Result<Vec<(Field, Option<Score>)>> {
+        let field_name = match field_name {
+            Some(field_name) => field_name,
+            None if self.default_fields.is_empty() => {
+                return Err(crate::error::invalid_query("No default search field".to_string()))
+            }
+            None => return Ok(self.default_fields.clone()),
+        };
+        let field = get_field(self.schema, &field_name)?;
+        if !self.schema.get_field_entry(field).is_indexed() {
+            return Err(crate::error::field_not_indexed(field_name));
+        }
+        let boost = self
+            .default_fields
+            .iter()
+            .find(|(default_field, _)| *default_field == field)
+            .and_then(|(_, boost)| *boost);
+        Ok(vec![(field, boost)])
+    }
+
+    fn literal_query(&self, field: Field, text: &str) -> crate::Result<Option<Box<dyn Query>>> {
+        Ok(match self.schema.get_field_entry(field).field_type() {
+            FieldType::Str(_) => {
+                let analyzer = get_analyzer(self.schema, field, self.tokenizers)?;
+                phrase_query(field, &analyzer, text)?
+            }
+            field_type => {
+                let term = make_term(field, field_type, text)?;
+                Some(Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs)))
+            }
+        })
+    }
+
+    fn bound(&self, field: Field, bound: &UserInputBound) -> crate::Result<Bound<Term>> {
+        let text = match bound {
+            UserInputBound::Inclusive(text) | UserInputBound::Exclusive(text) if text != "*" => text,
+            _ => return Ok(Bound::Unbounded),
+        };
+        let term = match self.schema.get_field_entry(field).field_type() {
+            FieldType::Str(_) => {
+                let analyzer = get_analyzer(self.schema, field, self.tokenizers)?;
+                // the word itself comes first, before its synonyms
+                match &analyze(field, &analyzer, text)[..] {
+                    [(_, terms)] => terms[0].clone(),
+                    _ => {
+                        return Err(crate::error::invalid_query(format!(
+                            "Range bound '{}' is not a single term",
+                            text
+                        )))
+                    }
+                }
+            }
+            field_type => make_term(field, field_type, text)?,
+        };
+        Ok(match bound {
+            UserInputBound::Exclusive(_) => Bound::Excluded(term),
+            _ => Bound::Included(term),
+        })
+    }
+}
+
+fn make_field_term(schema: &Schema, field_name: &str, value: &str) -> crate::Result<Term> {
+    let field = get_field(schema, field_name)?;
+    make_term(field, schema.get_field_entry(field).field_type(), value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tantivy::collector::TopDocs;
+    use tantivy::query::QueryParser;
+    use tantivy::schema::{INDEXED, STRING, TEXT};
+    use tantivy::tokenizer::SimpleTokenizer;
+    use tantivy::{doc, Index};
+    use crate::index_config::IndexSettings;
+
+    use crate::synonyms::{SynonymFilter, Synonyms};
+
+    fn test_index() -> Index {
+        let mut schema = Schema::builder();
+        schema.add_u64_field("id", INDEXED);
+        schema.add_facet_field("category", INDEXED);
+        schema.add_text_field("tag", STRING);
+        schema.add_text_field("text", TEXT);
+        Index::create_in_ram(schema.build())
+    }
+
+    #[test]
+    fn test_make_facet_term() {
+        let schema = test_index().schema();
+        let field = schema.get_field("category").unwrap();
+        let field_type = schema.get_field_entry(field).field_type();
+
+        let term = make_term(field, field_type, "/electronics/phones").unwrap();
+        assert_eq!(term, Term::from_facet(field, &Facet::from("/electronics/phones")));
+        assert!(make_term(field, field_type, "electronics").is_err());
+    }
+
+    #[test]
+    fn test_query_dsl_compile() {
+        let query = r#"
+{
+    "bool": {
+        "must": [{ "phrase": { "field": "text", "text": "hello world" } }],
+        "should": [{ "prefix": { "field": "tag", "value": "ru" } }],
+        "must_not": [{ "terms": { "field": "id", "values": [1, "2"] } }],
+        "filter": [{ "range": { "field": "id", "gte": 10, "lt": 20 } }]
+    }
+}
+        "#;
+        let query: QueryDsl = serde_json::from_str(query).unwrap();
+        let index = test_index();
+        assert!(query.compile(&index.schema(), index.tokenizers()).is_ok());
+    }
+
+    #[test]
+    fn test_query_dsl_rejects_invalid_fields() {
+        let index = test_index();
+
+        let query: QueryDsl = serde_json::from_str(r#"{ "exists": { "field": "nope" } }"#).unwrap();
+        assert!(query.compile(&index.schema(), index.tokenizers()).is_err());
+
+        let query: QueryDsl =
+            serde_json::from_str(r#"{ "fuzzy": { "field": "id", "value": "1" } }"#).unwrap();
+        assert!(query.compile(&index.schema(), index.tokenizers()).is_err());
+
+        let query: QueryDsl =
+            serde_json::from_str(r#"{ "range": { "field": "id", "gt": 1, "gte": 1 } }"#).unwrap();
+        assert!(query.compile(&index.schema(), index.tokenizers()).is_err());
+    }
+
+    #[test]
+    fn test_phrase_with_synonyms() {
+        let index = test_index();
+        let synonyms = Synonyms::parse(vec!["laptop, notebook"]).unwrap();
+        index.tokenizers().register(
+            "default",
+            TextAnalyzer::from(SimpleTokenizer).filter(SynonymFilter::new(synonyms)),
+        );
+        let query: QueryDsl =
+            serde_json::from_str(r#"{ "phrase": { "field": "text", "text": "cheap laptop" } }"#).unwrap();
+        let query = query.compile(&index.schema(), index.tokenizers()).unwrap();
+        let phrases = query.downcast::<BooleanQuery>().ok().unwrap();
+        assert_eq!(phrases.clauses().len(), 2);
+
+        // 2^7 phrases, over the limit
+        let query: QueryDsl = serde_json::from_str(
+            r#"{ "phrase": { "field": "text", "text": "laptop laptop laptop laptop laptop laptop laptop" } }"#,
+        )
+        .unwrap();
+        assert!(query.compile(&index.schema(), index.tokenizers()).is_err());
+    }
+
+    /// Results of the parser and of the tantivy one, which have no reason to differ
+    /// when no token has alternatives
+    #[test]
+    fn test_query_string_parity() {
+        let mut schema = Schema::builder();
+        let id = schema.add_u64_field("id", INDEXED);
+        let tag = schema.add_text_field("tag", STRING);
+        let title = schema.add_text_field("title", TEXT);
+        let text = schema.add_text_field("text", TEXT);
+        let schema = schema.build();
+        let index = Index::builder()
+            .settings(IndexSettings::default().tantivy)
+            .schema(schema.clone())
+            .create_in_ram()
+            .unwrap();
+        let mut writer = index.writer_with_num_threads(1, 15_000_000).unwrap();
+        let docs = [
+            (1, "rust", "hello world", "the quick brown fox"),
+            (2, "go", "hello", "jumps over the lazy dog"),
+            (3, "rust", "world news", "hello brown dog"),
+            (4, "java", "lazy fox", "world of hello"),
+        ];
+        for (key, tag_value, title_value, text_value) in docs.iter() {
+            writer.add_document(doc!(id => *key as u64, tag => *tag_value, title => *title_value, text => *text_value));
+        }
+        writer.commit().unwrap();
+        let searcher = index.reader().unwrap().searcher();
+
+        let queries = [
+            "hello",
+            "hello world",
+            "hello -fox",
+            "+hello +brown",
+            "\"hello world\"",
+            "title:hello^3 text:dog",
+            "(hello OR lazy) AND tag:rust",
+            "id:[2 TO 3]",
+            "id:{1 TO 4]",
+            "title:[hello TO lazy]",
+            "*",
+        ];
+        for conjunction in [false, true] {
+            let default_fields = vec![(title, Some(2.0)), (text, None)];
+            let parser = QueryStringParser::new(&schema, index.tokenizers(), default_fields, conjunction);
+            let mut tantivy_parser = QueryParser::for_index(&index, vec![title, text]);
+            tantivy_parser.set_field_boost(title, 2.0);
+            if conjunction {
+                tantivy_parser.set_conjunction_by_default();
+            }
+            for query in queries.iter() {
+                let top_docs = |query: &dyn Query| {
+                    searcher
+                        .search(query, &TopDocs::with_limit(10))
+                        .unwrap()
+                        .into_iter()
+                        .map(|(score, doc)| ((score * 1000.0).round() as i64, doc))
+                        .collect::<Vec<_>>()
+                };
+                assert_eq!(
+                    top_docs(parser.parse(query).unwrap().as_ref()),
+                    top_docs(tantivy_parser.parse_query(query).unwrap().as_ref()),
+                    "{} with conjunction {}",
+                    query,
+                    conjunction
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_query_string_with_synonyms() {
+        let index = test_index();
+        let schema = index.schema();
+        let synonyms = Synonyms::parse(vec!["laptop, notebook"]).unwrap();
+        index.tokenizers().register(
+            "default",
+            TextAnalyzer::from(SimpleTokenizer).filter(SynonymFilter::new(synonyms)),
+        );
+        let text = schema.get_field("text").unwrap();
+        let parser = QueryStringParser::new(&schema, index.tokenizers(), vec![(text, None)], false);
+
+        // alternatives of a word instead of a phrase of the word and its synonyms
+        let query = parser.parse("laptop").unwrap();
+        let words = query.downcast::<BooleanQuery>().ok().unwrap();
+        assert_eq!(words.clauses().len(), 2);
+        assert!(words.clauses()[0].1.is::<TermQuery>());
+
+        assert!(parser.parse("id:[1 TO 10] AND tag:rust AND category:/books").is_ok());
+        assert!(parser.parse("-laptop").is_err());
+        assert!(parser.parse("id:one").is_err());
+        assert!(parser.parse("nope:laptop").is_err());
+    }
+}
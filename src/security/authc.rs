@@ -70,10 +70,10 @@ impl AuthService {
         let users = self.users.read().unwrap();
         let valid_password = users.get(creds.user_id().as_ref());
 
-        match (creds.password(), valid_password) {
-            (Some(password), Some(valid_password)) if password == valid_password => true,
-            _ => false,
-        }
+        matches!(
+            (creds.password(), valid_password),
+            (Some(password), Some(valid_password)) if password == valid_password
+        )
     }
 
     pub fn add_user(&self, AddUserReq { name, password }: AddUserReq) -> Result<()> {
@@ -121,7 +121,7 @@ pub async fn authentication_handler(
     } else {
         let config = req
             .app_data::<Config>()
-            .map(|conf| conf.clone())
+            .cloned()
             .unwrap_or_default();
         Err(AuthenticationError::from(config).into())
     }
@@ -0,0 +1,353 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::convert::TryInto;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tantivy::collector::{Collector, SegmentCollector};
+use tantivy::schema::Schema;
+use tantivy::{f64_to_u64, DocAddress, DocId, Score, SegmentOrdinal, SegmentReader};
+
+use crate::fast_field::{BucketKey, FastField, FastType, FastValues};
+
+/// Sorts by relevance when used as a sort field
+pub const SCORE_FIELD: &str = "_score";
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct SortField {
+    /// fast field or `_score`
+    pub field: String,
+    /// ascending by default, descending for `_score`
+    #[serde(default)]
+    pub order: Option<Order>,
+}
+
+/// Order preserving representation of a sort value, missing values go last
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SortValue {
+    Value(u64),
+    Missing,
+}
+
+enum SortKey {
+    Score,
+    Field(FastField),
+}
+
+struct SortBy {
+    key: SortKey,
+    order: Order,
+}
+
+/// Sort fields validated against the schema
+pub struct Sort(Vec<SortBy>);
+
+impl Sort {
+    pub fn new(schema: &Schema, fields: &[SortField]) -> crate::Result<Self> {
+        fields
+            .iter()
+            .map(|sort| {
+                let (key, default_order) = if sort.field == SCORE_FIELD {
+                    (SortKey::Score, Order::Desc)
+                } else {
+                    (SortKey::Field(FastField::numeric(schema, &sort.field)?), Order::Asc)
+                };
+                Ok(SortBy {
+                    key,
+                    order: sort.order.unwrap_or(default_order),
+                })
+            })
+            .collect::<crate::Result<_>>()
+            .map(Self)
+    }
+
+    /// Sort by descending relevance
+    pub fn relevance() -> Self {
+        Self(vec![SortBy {
+            key: SortKey::Score,
+            order: Order::Desc,
+        }])
+    }
+
+    /// Types of the sort fields, `None` for the score
+    pub fn fast_types(&self) -> Vec<Option<FastType>> {
+        self.0
+            .iter()
+            .map(|sort| match &sort.key {
+                SortKey::Score => None,
+                SortKey::Field(field) => Some(field.fast_type()),
+            })
+            .collect()
+    }
+
+    /// Converts the order preserving representation back into the sort values
+    pub fn values(&self, keys: &[SortValue]) -> Vec<Option<BucketKey>> {
+        self.0
+            .iter()
+            .zip(keys)
+            .map(|(sort, key)| {
+                let raw = match (key, sort.order) {
+                    (SortValue::Missing, _) => return None,
+                    (SortValue::Value(raw), Order::Asc) => *raw,
+                    (SortValue::Value(raw), Order::Desc) => !raw,
+                };
+                Some(match &sort.key {
+                    SortKey::Score => BucketKey::F64(raw),
+                    SortKey::Field(field) => field.key(raw),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Position of a document in the sort order, ties are broken by doc address
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SortPosition {
+    pub keys: Vec<SortValue>,
+    pub address: DocAddress,
+}
+
+impl SortPosition {
+    /// Encodes the position as an opaque `search_after` cursor. The document address
+    /// only makes sense for the searcher it comes from, as commits and merges
+    /// renumber the documents, so the cursor is meant to be used with a point in time.
+    pub fn cursor(&self) -> String {
+        let mut bytes = Vec::with_capacity(self.keys.len() * 9 + 8);
+        for key in &self.keys {
+            match key {
+                SortValue::Value(raw) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(&raw.to_be_bytes());
+                }
+                SortValue::Missing => bytes.push(0),
+            }
+        }
+        bytes.extend_from_slice(&self.address.segment_ord.to_be_bytes());
+        bytes.extend_from_slice(&self.address.doc_id.to_be_bytes());
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn from_cursor(cursor: &str, sort: &Sort) -> crate::Result<Self> {
+        base64::decode_config(cursor, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|bytes| Self::decode(&bytes, sort.0.len()))
+            .ok_or_else(|| {
+                crate::error::invalid_query(format!("Invalid search_after cursor '{}'", cursor))
+            })
+    }
+
+    fn decode(mut bytes: &[u8], key_count: usize) -> Option<Self> {
+        fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+            let taken = bytes.get(..N)?.try_into().ok()?;
+            *bytes = &bytes[N..];
+            Some(taken)
+        }
+        let keys = (0..key_count)
+            .map(|_| match take::<1>(&mut bytes)? {
+                [0] => Some(SortValue::Missing),
+                [1] => Some(SortValue::Value(u64::from_be_bytes(take(&mut bytes)?))),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        let segment_ord = u32::from_be_bytes(take(&mut bytes)?);
+        let doc_id = u32::from_be_bytes(take(&mut bytes)?);
+        bytes.is_empty().then(|| Self {
+            keys,
+            address: DocAddress::new(segment_ord, doc_id),
+        })
+    }
+}
+
+pub struct SortedDoc {
+    pub score: Score,
+    pub position: SortPosition,
+}
+
+impl PartialEq for SortedDoc {
+    fn eq(&self, other: &Self) -> bool {
+        self.position == other.position
+    }
+}
+
+impl Eq for SortedDoc {}
+
+impl PartialOrd for SortedDoc {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SortedDoc {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.position.cmp(&other.position)
+    }
+}
+
+/// Top documents ordered by several sort fields, ties are broken by doc address
+pub struct SortCollector {
+    sort: Arc<Sort>,
+    limit: usize,
+    offset: usize,
+    /// only the documents after this position are collected
+    after: Option<Arc<SortPosition>>,
+}
+
+impl SortCollector {
+    pub fn new(sort: Arc<Sort>, limit: usize, offset: usize, after: Option<SortPosition>) -> Self {
+        Self {
+            sort,
+            limit,
+            offset,
+            after: after.map(Arc::new),
+        }
+    }
+}
+
+impl Collector for SortCollector {
+    type Fruit = Vec<SortedDoc>;
+    type Child = SortSegmentCollector;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        segment: &SegmentReader,
+    ) -> tantivy::Result<Self::Child> {
+        let keys = self.sort.0
+            .iter()
+            .map(|sort| {
+                Ok(match &sort.key {
+                    SortKey::Score => SegmentSortKey::Score,
+                    SortKey::Field(field) => {
+                        SegmentSortKey::Field(Box::new(field.reader(segment)?))
+                    }
+                })
+            })
+            .collect::<tantivy::Result<_>>()?;
+        Ok(SortSegmentCollector {
+            segment_ord: segment_local_id,
+            keys,
+            orders: self.sort.0.iter().map(|sort| sort.order).collect(),
+            size: self.limit + self.offset,
+            after: self.after.clone(),
+            top: BinaryHeap::new(),
+            doc_keys: Vec::new(),
+            buf: Vec::new(),
+        })
+    }
+
+    /// Scores are returned even when not sorting by them
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(&self, segment_fruits: Vec<Vec<SortedDoc>>) -> tantivy::Result<Vec<SortedDoc>> {
+        let mut docs = segment_fruits.into_iter().flatten().collect::<Vec<_>>();
+        docs.sort_unstable();
+        Ok(docs.into_iter().skip(self.offset).take(self.limit).collect())
+    }
+}
+
+enum SegmentSortKey {
+    Score,
+    Field(Box<FastValues>),
+}
+
+pub struct SortSegmentCollector {
+    segment_ord: SegmentOrdinal,
+    keys: Vec<SegmentSortKey>,
+    orders: Vec<Order>,
+    size: usize,
+    after: Option<Arc<SortPosition>>,
+    /// the `size` first documents, the last one on top
+    top: BinaryHeap<SortedDoc>,
+    doc_keys: Vec<SortValue>,
+    buf: Vec<u64>,
+}
+
+impl SegmentCollector for SortSegmentCollector {
+    type Fruit = Vec<SortedDoc>;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        self.doc_keys.clear();
+        for (key, order) in self.keys.iter().zip(&self.orders) {
+            let raw = match key {
+                SegmentSortKey::Score => Some(f64_to_u64(score as f64)),
+                // multivalued fields are sorted by their first value in the order
+                SegmentSortKey::Field(values) => {
+                    values.values(doc, &mut self.buf);
+                    match order {
+                        Order::Asc => self.buf.iter().min(),
+                        Order::Desc => self.buf.iter().max(),
+                    }
+                    .copied()
+                }
+            };
+            self.doc_keys.push(match (raw, order) {
+                (None, _) => SortValue::Missing,
+                (Some(raw), Order::Asc) => SortValue::Value(raw),
+                (Some(raw), Order::Desc) => SortValue::Value(!raw),
+            });
+        }
+
+        let address = DocAddress::new(self.segment_ord, doc);
+        let position = (self.doc_keys.as_slice(), address);
+        if let Some(after) = &self.after {
+            if position <= (after.keys.as_slice(), after.address) {
+                return;
+            }
+        }
+        if self.top.len() >= self.size {
+            match self.top.peek() {
+                Some(last) if position < (last.position.keys.as_slice(), last.position.address) => {
+                    self.top.pop();
+                }
+                _ => return,
+            }
+        }
+        self.top.push(SortedDoc {
+            score,
+            position: SortPosition {
+                keys: self.doc_keys.clone(),
+                address,
+            },
+        });
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.top.into_vec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let mut schema = Schema::builder();
+        schema.add_f64_field("price", tantivy::schema::FAST);
+        let schema = schema.build();
+        let fields = vec![
+            SortField { field: "price".to_string(), order: None },
+            SortField { field: SCORE_FIELD.to_string(), order: None },
+        ];
+        let sort = Sort::new(&schema, &fields).unwrap();
+
+        let position = SortPosition {
+            keys: vec![SortValue::Missing, SortValue::Value(!f64_to_u64(1.5))],
+            address: DocAddress::new(2, 42),
+        };
+        let decoded = SortPosition::from_cursor(&position.cursor(), &sort).unwrap();
+        assert_eq!(decoded, position);
+
+        assert!(SortPosition::from_cursor("AAAA", &sort).is_err());
+        assert!(SortPosition::from_cursor(&position.cursor(), &Sort::relevance()).is_err());
+    }
+}
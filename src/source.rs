@@ -0,0 +1,115 @@
+use serde::Deserialize;
+use tantivy::schema::{Document, NamedFieldDocument, Schema};
+
+use crate::utils::wildcard::Wildcard;
+
+/// Stored fields to return in search hits, `*` in a pattern matches any characters
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+pub enum SourceReq {
+    /// comma separated patterns of the fields to include, as given in a query string
+    Patterns(String),
+    Includes(Vec<String>),
+    Filter {
+        /// every field when empty
+        #[serde(default)]
+        includes: Vec<String>,
+        #[serde(default)]
+        excludes: Vec<String>,
+    },
+}
+
+/// Source request resolved against the schema
+pub struct SourceFilter {
+    /// whether each field is returned, by field id
+    selected: Vec<bool>,
+}
+
+impl SourceFilter {
+    pub fn new(schema: &Schema, req: &SourceReq) -> crate::Result<Self> {
+        let (includes, excludes) = match req {
+            SourceReq::Patterns(patterns) => (
+                patterns
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|pattern| !pattern.is_empty())
+                    .map(Wildcard::new)
+                    .collect(),
+                Vec::new(),
+            ),
+            SourceReq::Includes(includes) => (wildcards(includes), Vec::new()),
+            SourceReq::Filter { includes, excludes } => (wildcards(includes), wildcards(excludes)),
+        };
+        // a plain field name is most likely a typo when it is not in the schema
+        for pattern in includes.iter().chain(&excludes) {
+            if let Wildcard::Exact(name) = pattern {
+                if schema.get_field(name).is_none() {
+                    return Err(crate::error::field_not_exist(name.clone()));
+                }
+            }
+        }
+        let selected = schema
+            .fields()
+            .map(|(_, entry)| {
+                let name = entry.name();
+                (includes.is_empty() || includes.iter().any(|pattern| pattern.matches(name)))
+                    && !excludes.iter().any(|pattern| pattern.matches(name))
+            })
+            .collect();
+        Ok(Self { selected })
+    }
+
+    /// Converts the selected fields of `doc`
+    pub fn to_named_doc(&self, schema: &Schema, doc: &Document) -> NamedFieldDocument {
+        let field_values = doc
+            .field_values()
+            .iter()
+            .filter(|field_value| self.selected[field_value.field().field_id() as usize])
+            .cloned()
+            .collect::<Vec<_>>();
+        schema.to_named_doc(&Document::from(field_values))
+    }
+}
+
+fn wildcards(patterns: &[String]) -> Vec<Wildcard> {
+    patterns.iter().map(|pattern| Wildcard::new(pattern)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tantivy::schema::{STORED, TEXT};
+
+    #[test]
+    fn test_source_filter() {
+        let mut schema = Schema::builder();
+        let id = schema.add_u64_field("id", STORED);
+        let title = schema.add_text_field("title", TEXT | STORED);
+        let body = schema.add_text_field("body", TEXT | STORED);
+        let body_html = schema.add_text_field("body_html", STORED);
+        let schema = schema.build();
+
+        let mut doc = Document::new();
+        doc.add_u64(id, 1);
+        doc.add_text(title, "title");
+        doc.add_text(body, "body");
+        doc.add_text(body_html, "<p>body</p>");
+
+        let names = |req: SourceReq| -> Vec<String> {
+            let filter = SourceFilter::new(&schema, &req).unwrap();
+            filter.to_named_doc(&schema, &doc).0.into_keys().collect()
+        };
+        assert_eq!(names(SourceReq::Patterns("id, body*".to_string())), ["body", "body_html", "id"]);
+        assert_eq!(names(SourceReq::Includes(vec!["*itl*".to_string()])), ["title"]);
+        assert_eq!(
+            names(SourceReq::Filter {
+                includes: Vec::new(),
+                excludes: vec!["*_html".to_string()],
+            }),
+            ["body", "id", "title"]
+        );
+
+        assert!(SourceFilter::new(&schema, &SourceReq::Includes(vec!["text".to_string()])).is_err());
+        assert!(SourceFilter::new(&schema, &SourceReq::Includes(vec!["text*".to_string()])).is_ok());
+    }
+}
@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+use tantivy::tokenizer::StopWordFilter;
+
+/// Snowball list, without the letter ё like the texts it was made from
+const RUSSIAN: &[&str] = &[
+    "и", "в", "во", "не", "что", "он", "на", "я", "с", "со", "как", "а", "то", "все", "она",
+    "так", "его", "но", "да", "ты", "к", "у", "же", "вы", "за", "бы", "по", "только", "ее",
+    "мне", "было", "вот", "от", "меня", "еще", "нет", "о", "из", "ему", "теперь", "когда",
+    "даже", "ну", "вдруг", "ли", "если", "уже", "или", "ни", "быть", "был", "него", "до",
+    "вас", "нибудь", "опять", "уж", "вам", "ведь", "там", "потом", "себя", "ничего", "ей",
+    "может", "они", "тут", "где", "есть", "надо", "ней", "для", "мы", "тебя", "их", "чем",
+    "была", "сам", "чтоб", "без", "будто", "чего", "раз", "тоже", "себе", "под", "будет", "ж",
+    "тогда", "кто", "этот", "того", "потому", "этого", "какой", "совсем", "ним", "здесь",
+    "этом", "один", "почти", "мой", "тем", "чтобы", "нее", "сейчас", "были", "куда", "зачем",
+    "всех", "никогда", "можно", "при", "наконец", "два", "об", "другой", "хоть", "после",
+    "над", "больше", "тот", "через", "эти", "нас", "про", "всего", "них", "какая", "много",
+    "разве", "три", "эту", "моя", "впрочем", "хорошо", "свою", "этой", "перед", "иногда",
+    "лучше", "чуть", "том", "нельзя", "такой", "им", "более", "всегда", "конечно", "всю",
+    "между",
+];
+
+/// Languages with a built-in list of lowercase stop words
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StopWordsLanguage {
+    English,
+    Russian,
+}
+
+impl StopWordsLanguage {
+    pub fn filter(self) -> StopWordFilter {
+        match self {
+            // tantivy's own english list
+            StopWordsLanguage::English => StopWordFilter::default(),
+            StopWordsLanguage::Russian => {
+                StopWordFilter::remove(RUSSIAN.iter().map(|word| word.to_string()).collect())
+            }
+        }
+    }
+}
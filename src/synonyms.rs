@@ -0,0 +1,228 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use tantivy::tokenizer::{BoxTokenStream, Token, TokenFilter, TokenStream};
+
+/// dir of the data dir holding the synonyms files
+pub const SYNONYMS_DIR: &str = "synonyms";
+
+/// Replacements of a word, including the word itself unless a one-way rule replaces it
+#[derive(Debug, Default)]
+pub struct Synonyms(HashMap<String, Vec<String>>);
+
+impl Synonyms {
+    /// Parses Solr style rules: `laptop, notebook` makes the words equivalent and
+    /// `tv, telly => television` replaces the words on the left by the words on the right
+    pub fn parse<'a>(rules: impl IntoIterator<Item = &'a str>) -> crate::Result<Self> {
+        let mut synonyms = Self::default();
+        for (line, rule) in rules.into_iter().enumerate() {
+            let rule = rule.trim();
+            if rule.is_empty() || rule.starts_with('#') {
+                continue;
+            }
+            let (words, replacements) = match rule.split_once("=>") {
+                Some((words, replacements)) => (parse_words(line, words)?, parse_words(line, replacements)?),
+                None => {
+                    let words = parse_words(line, rule)?;
+                    (words.clone(), words)
+                }
+            };
+            for word in words {
+                let entry = synonyms.0.entry(word).or_default();
+                for replacement in &replacements {
+                    if !entry.contains(replacement) {
+                        entry.push(replacement.clone());
+                    }
+                }
+            }
+        }
+        Ok(synonyms)
+    }
+
+    /// Reads the rules of a file of the synonyms dir, one rule per line
+    pub fn load(data_dir: &Path, path: &Path) -> crate::Result<Self> {
+        Self::parse(read(data_dir, path)?.lines())
+    }
+
+    /// Rules of a file of the synonyms dir without its comments, checked like by `load`
+    pub fn read_rules(data_dir: &Path, path: &Path) -> crate::Result<Vec<String>> {
+        let rules = read(data_dir, path)?;
+        Self::parse(rules.lines())?;
+        Ok(rules
+            .lines()
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty() && !rule.starts_with('#'))
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Adds the rules of `other`, for words of both the replacements are merged
+    pub fn extend(&mut self, other: Synonyms) {
+        for (word, replacements) in other.0 {
+            let entry = self.0.entry(word).or_default();
+            for replacement in replacements {
+                if !entry.contains(&replacement) {
+                    entry.push(replacement);
+                }
+            }
+        }
+    }
+}
+
+fn read(data_dir: &Path, path: &Path) -> crate::Result<String> {
+    std::fs::read_to_string(resolve(data_dir, path)?).map_err(|err| {
+        crate::error::invalid_settings(format!("Synonyms file is not readable: {}", err))
+    })
+}
+
+/// Path of a synonyms file, which must stay in the synonyms dir: the files may be
+/// readable by the server only, so the errors never show their content
+fn resolve(data_dir: &Path, path: &Path) -> crate::Result<PathBuf> {
+    let outside = || {
+        crate::error::invalid_settings(format!(
+            "Synonyms path '{}' must be relative to the '{}' dir of the data dir",
+            path.display(),
+            SYNONYMS_DIR
+        ))
+    };
+    if !path.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(outside());
+    }
+    let not_found = |err: std::io::Error| {
+        crate::error::invalid_settings(format!("Synonyms file '{}': {}", path.display(), err))
+    };
+    // symbolic links may still lead out of the dir
+    let dir = data_dir.join(SYNONYMS_DIR).canonicalize().map_err(not_found)?;
+    let path = dir.join(path).canonicalize().map_err(not_found)?;
+    if !path.starts_with(&dir) {
+        return Err(outside());
+    }
+    Ok(path)
+}
+
+/// Single words only, the filter sees one token at a time
+fn parse_words(line: usize, words: &str) -> crate::Result<Vec<String>> {
+    let words = words.split(',').map(str::trim).map(str::to_string).collect::<Vec<_>>();
+    if words.iter().any(|word| word.is_empty() || word.contains(char::is_whitespace)) {
+        return Err(crate::error::invalid_settings(format!(
+            "Synonym rule {} is invalid, rules are comma separated single words",
+            line + 1
+        )));
+    }
+    Ok(words)
+}
+
+/// Emits the replacements of a token at its position, so that phrases still match
+#[derive(Clone)]
+pub struct SynonymFilter {
+    synonyms: Arc<Synonyms>,
+}
+
+impl SynonymFilter {
+    pub fn new(synonyms: Synonyms) -> Self {
+        Self {
+            synonyms: Arc::new(synonyms),
+        }
+    }
+}
+
+impl TokenFilter for SynonymFilter {
+    fn transform<'a>(&self, token_stream: BoxTokenStream<'a>) -> BoxTokenStream<'a> {
+        BoxTokenStream::from(SynonymFilterStream {
+            synonyms: self.synonyms.clone(),
+            tail: token_stream,
+            token: Token::default(),
+            pending: VecDeque::new(),
+        })
+    }
+}
+
+pub struct SynonymFilterStream<'a> {
+    synonyms: Arc<Synonyms>,
+    tail: BoxTokenStream<'a>,
+    token: Token,
+    /// replacements not emitted yet of the current token
+    pending: VecDeque<String>,
+}
+
+impl<'a> TokenStream for SynonymFilterStream<'a> {
+    fn advance(&mut self) -> bool {
+        if let Some(text) = self.pending.pop_front() {
+            self.token.text = text;
+            return true;
+        }
+        if !self.tail.advance() {
+            return false;
+        }
+        self.token = self.tail.token().clone();
+        if let Some(replacements) = self.synonyms.0.get(&self.token.text) {
+            self.pending.extend(replacements.iter().cloned());
+            if let Some(text) = self.pending.pop_front() {
+                self.token.text = text;
+            }
+        }
+        true
+    }
+
+    fn token(&self) -> &Token {
+        &self.token
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.token
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tantivy::tokenizer::{SimpleTokenizer, TextAnalyzer};
+
+    #[test]
+    fn test_synonym_filter() {
+        let synonyms = Synonyms::parse(vec!["laptop, notebook", "tv => television", "# comment"]).unwrap();
+        let analyzer = TextAnalyzer::from(SimpleTokenizer).filter(SynonymFilter::new(synonyms));
+        let mut tokens = Vec::new();
+        analyzer
+            .token_stream("cheap laptop tv")
+            .process(&mut |token| tokens.push((token.text.clone(), token.position)));
+        assert_eq!(
+            tokens,
+            vec![
+                ("cheap".to_string(), 0),
+                ("laptop".to_string(), 1),
+                ("notebook".to_string(), 1),
+                ("television".to_string(), 2),
+            ]
+        );
+
+        assert!(Synonyms::parse(vec!["smart phone => smartphone"]).is_err());
+        assert!(Synonyms::parse(vec!["a, , b"]).is_err());
+    }
+
+    #[test]
+    fn test_load_from_synonyms_dir() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let dir = data_dir.path().join(SYNONYMS_DIR);
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("en.txt"), "laptop, notebook\n").unwrap();
+        std::fs::write(data_dir.path().join("secret.json"), "secret password\n").unwrap();
+        std::os::unix::fs::symlink(data_dir.path().join("secret.json"), dir.join("link.txt")).unwrap();
+
+        assert!(Synonyms::load(data_dir.path(), Path::new("en.txt")).is_ok());
+        assert_eq!(
+            Synonyms::read_rules(data_dir.path(), Path::new("en.txt")).unwrap(),
+            vec!["laptop, notebook".to_string()]
+        );
+        assert!(Synonyms::load(data_dir.path(), Path::new("../secret.json")).is_err());
+        assert!(Synonyms::load(data_dir.path(), &data_dir.path().join("secret.json")).is_err());
+        let err = Synonyms::load(data_dir.path(), Path::new("link.txt")).unwrap_err();
+        assert!(!err.to_string().contains("secret password"), "{}", err);
+
+        std::fs::write(dir.join("invalid.txt"), "# comment\nsmart phone => smartphone\n").unwrap();
+        let err = Synonyms::load(data_dir.path(), Path::new("invalid.txt")).unwrap_err();
+        assert!(!err.to_string().contains("smart"), "{}", err);
+        assert!(err.to_string().contains("rule 2"), "{}", err);
+    }
+}
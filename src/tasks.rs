@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tantivy::chrono::Utc;
+
+/// number of finished tasks kept to be looked up
+const MAX_FINISHED_TASKS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Task {
+    pub id: u64,
+    pub action: String,
+    pub index: String,
+    pub status: TaskStatus,
+    /// RFC 3339 start time
+    pub started: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    tasks: BTreeMap<u64, Task>,
+    next_id: u64,
+}
+
+/// Long running operations started by a request, tracked until they finish
+#[derive(Default)]
+pub struct Tasks {
+    state: Mutex<State>,
+}
+
+impl Tasks {
+    pub fn start(&self, action: &str, index: &str) -> crate::Result<Task> {
+        let mut state = self.state.lock().map_err(crate::error::lock_poisoned)?;
+        state.next_id += 1;
+        let task = Task {
+            id: state.next_id,
+            action: action.to_string(),
+            index: index.to_string(),
+            status: TaskStatus::Running,
+            started: Utc::now().to_rfc3339(),
+            finished: None,
+            error: None,
+        };
+        state.tasks.insert(task.id, task.clone());
+        Ok(task)
+    }
+
+    pub fn finish(&self, id: u64, result: crate::Result<()>) -> crate::Result<()> {
+        let mut state = self.state.lock().map_err(crate::error::lock_poisoned)?;
+        if let Some(task) = state.tasks.get_mut(&id) {
+            task.finished = Some(Utc::now().to_rfc3339());
+            match result {
+                Ok(()) => task.status = TaskStatus::Completed,
+                Err(err) => {
+                    log::error!("Task {} '{}' of index '{}' failed: {}", id, task.action, task.index, err);
+                    task.status = TaskStatus::Failed;
+                    task.error = Some(err.to_string());
+                }
+            }
+        }
+        // ids are increasing, so the first finished tasks are the oldest
+        let finished = state
+            .tasks
+            .values()
+            .filter(|task| task.status != TaskStatus::Running)
+            .map(|task| task.id)
+            .collect::<Vec<_>>();
+        for id in finished.iter().take(finished.len().saturating_sub(MAX_FINISHED_TASKS)) {
+            state.tasks.remove(id);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, id: u64) -> crate::Result<Task> {
+        let state = self.state.lock().map_err(crate::error::lock_poisoned)?;
+        state.tasks.get(&id).cloned().ok_or_else(|| crate::error::task_not_found(id))
+    }
+
+    pub fn list(&self) -> crate::Result<Vec<Task>> {
+        let state = self.state.lock().map_err(crate::error::lock_poisoned)?;
+        Ok(state.tasks.values().cloned().collect())
+    }
+}
@@ -0,0 +1,138 @@
+use regex::Regex;
+use tantivy::tokenizer::{BoxTokenStream, Token, TokenStream, Tokenizer};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Splits the text on whitespaces only, so that identifiers like `foo.bar_baz` stay whole
+#[derive(Clone)]
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn token_stream<'a>(&self, text: &'a str) -> BoxTokenStream<'a> {
+        let words = text
+            .split(char::is_whitespace)
+            .filter(|word| !word.is_empty())
+            // the words are slices of the text
+            .map(move |word| (word.as_ptr() as usize - text.as_ptr() as usize, word));
+        BoxTokenStream::from(WordsTokenStream::new(words))
+    }
+}
+
+/// Tokens are the matches of a pattern
+#[derive(Clone)]
+pub struct RegexTokenizer {
+    regex: Regex,
+}
+
+impl RegexTokenizer {
+    pub fn new(pattern: &str) -> crate::Result<Self> {
+        let regex = Regex::new(pattern).map_err(|err| {
+            crate::error::invalid_settings(format!("Invalid tokenizer pattern '{}': {}", pattern, err))
+        })?;
+        Ok(Self { regex })
+    }
+}
+
+impl Tokenizer for RegexTokenizer {
+    fn token_stream<'a>(&self, text: &'a str) -> BoxTokenStream<'a> {
+        let words = self
+            .regex
+            .find_iter(text)
+            .filter(|word| !word.as_str().is_empty())
+            .map(|word| (word.start(), word.as_str()))
+            .collect::<Vec<_>>();
+        BoxTokenStream::from(WordsTokenStream::new(words.into_iter()))
+    }
+}
+
+/// Words found by the Unicode word boundaries of UAX#29, without punctuation
+/// and spaces. Ideographs of CJK texts are words of one character.
+#[derive(Clone)]
+pub struct UnicodeWordsTokenizer;
+
+impl Tokenizer for UnicodeWordsTokenizer {
+    fn token_stream<'a>(&self, text: &'a str) -> BoxTokenStream<'a> {
+        BoxTokenStream::from(WordsTokenStream::new(text.unicode_word_indices()))
+    }
+}
+
+/// Tokens of the words given with their byte offset in the text
+struct WordsTokenStream<I> {
+    words: I,
+    token: Token,
+}
+
+impl<I> WordsTokenStream<I> {
+    fn new(words: I) -> Self {
+        Self {
+            words,
+            token: Token::default(),
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = (usize, &'a str)>> TokenStream for WordsTokenStream<I> {
+    fn advance(&mut self) -> bool {
+        self.token.text.clear();
+        // the default position is usize::MAX, so the first token is at 0
+        self.token.position = self.token.position.wrapping_add(1);
+        match self.words.next() {
+            Some((offset, word)) => {
+                self.token.offset_from = offset;
+                self.token.offset_to = offset + word.len();
+                self.token.text.push_str(word);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn token(&self) -> &Token {
+        &self.token
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.token
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tokens(tokenizer: &dyn Tokenizer, text: &str) -> Vec<(String, usize, usize)> {
+        let mut tokens = Vec::new();
+        tokenizer.token_stream(text).process(&mut |token| {
+            tokens.push((token.text.clone(), token.offset_from, token.position))
+        });
+        tokens
+    }
+
+    #[test]
+    fn test_tokenizers() {
+        assert_eq!(
+            tokens(&WhitespaceTokenizer, " foo.bar_baz\tqux "),
+            vec![("foo.bar_baz".to_string(), 1, 0), ("qux".to_string(), 13, 1)]
+        );
+
+        let tokenizer = RegexTokenizer::new(r"[\w.]+").unwrap();
+        assert_eq!(
+            tokens(&tokenizer, "call foo.bar(baz)"),
+            vec![
+                ("call".to_string(), 0, 0),
+                ("foo.bar".to_string(), 5, 1),
+                ("baz".to_string(), 13, 2),
+            ]
+        );
+        assert!(RegexTokenizer::new("(").is_err());
+
+        assert_eq!(
+            tokens(&UnicodeWordsTokenizer, "Hello, 世界! can't"),
+            vec![
+                ("Hello".to_string(), 0, 0),
+                ("世".to_string(), 7, 1),
+                ("界".to_string(), 10, 2),
+                ("can't".to_string(), 15, 3),
+            ]
+        );
+    }
+}
@@ -1,3 +1,4 @@
 pub mod flags;
 pub mod json_file_storage;
 pub mod macros;
+pub mod wildcard;
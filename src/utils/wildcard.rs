@@ -0,0 +1,28 @@
+use regex::Regex;
+
+/// Name pattern where `*` matches any characters
+pub enum Wildcard {
+    Exact(String),
+    Pattern(Regex),
+}
+
+impl Wildcard {
+    pub fn new(pattern: &str) -> Self {
+        if !pattern.contains('*') {
+            return Wildcard::Exact(pattern.to_string());
+        }
+        let regex = pattern
+            .split('*')
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join(".*");
+        Wildcard::Pattern(Regex::new(&format!("^{}$", regex)).expect("escaped pattern"))
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Wildcard::Exact(pattern) => pattern == name,
+            Wildcard::Pattern(regex) => regex.is_match(name),
+        }
+    }
+}